@@ -0,0 +1,87 @@
+// Compares the old per-pixel fill_rect rendering with the streaming texture
+// used by Display. Run with `cargo bench`.
+extern crate chip8;
+extern crate sdl2;
+
+use chip8::display::{Display, SCALE};
+use chip8::gpu::{Gpu, HEIGHT, WIDTH};
+use chip8::osd::{Osd, Stats};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use std::time::Instant;
+
+static FRAMES: u32 = 2000;
+
+fn main() {
+    let sdl_context = sdl2::init().unwrap();
+
+    // a checkerboard lights half of the pixels
+    let mut gpu = Gpu::new();
+    for y in 0..HEIGHT {
+        for x in (y % 2..WIDTH).step_by(2) {
+            gpu.draw_sprite(x, y, &[0x80]);
+        }
+    }
+
+    // the same window and renderer as Display::new makes, so only the way
+    // of drawing differs
+    let mut canvas = sdl_context
+        .video()
+        .unwrap()
+        .window("fill_rect", WIDTH as u32 * SCALE, HEIGHT as u32 * SCALE)
+        .position_centered()
+        .resizable()
+        .opengl()
+        .build()
+        .unwrap()
+        .into_canvas()
+        .build()
+        .unwrap();
+
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        canvas.clear();
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        for i in 0..HEIGHT {
+            for j in 0..WIDTH {
+                if gpu.pixel(j, i) {
+                    let x = (j as i32) * (SCALE as i32);
+                    let y = (i as i32) * (SCALE as i32);
+                    canvas.fill_rect(Rect::new(x, y, SCALE, SCALE)).ok();
+                }
+            }
+        }
+        canvas.present();
+    }
+    report("fill_rect", start);
+    drop(canvas);
+
+    let mut display = Display::new(&sdl_context, "texture", SCALE);
+    let mut osd = Osd::new(Stats::default());
+    let start = Instant::now();
+    for frame in 0..FRAMES {
+        gpu.draw_flag = true;
+        display.render_screen(&mut gpu, frame as u64, &mut osd);
+    }
+    report("texture", start);
+
+    // the same with the stats drawn over it
+    osd.set_stats(Stats::all());
+    let start = Instant::now();
+    for frame in 0..FRAMES {
+        gpu.draw_flag = true;
+        osd.update(frame as u64, frame as u64 * 5);
+        display.render_screen(&mut gpu, frame as u64, &mut osd);
+    }
+    report("osd", start);
+}
+
+fn report(name: &str, start: Instant) {
+    let elapsed = start.elapsed();
+    println!(
+        "{:10} {:8.3} ms/frame",
+        name,
+        elapsed.as_secs_f64() * 1000.0 / FRAMES as f64
+    );
+}
@@ -0,0 +1,377 @@
+extern crate chip8;
+extern crate crossterm;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Color, Colors, Print, ResetColor, SetColors};
+use crossterm::{cursor, execute, queue, terminal};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::panic;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chip8::control::{self, Control};
+use chip8::cpu::Cpu;
+use chip8::gpu::WIDTH;
+use chip8::graphics::Protocol;
+use chip8::keymap::Keymap;
+use chip8::options::{self, Options};
+use chip8::palette::Palette;
+use chip8::recorder;
+use chip8::trace;
+use chip8::tui::{self, TextMode};
+
+// terminals only report key presses, so a key counts as held until this
+// long after its last press or autorepeat
+static KEY_HOLD: Duration = Duration::from_millis(150);
+
+// pixel scale for the graphics protocols
+static GRAPHICS_SCALE: usize = 8;
+
+// How the screen is drawn.
+#[derive(Clone, Copy)]
+enum Output {
+    Text(TextMode),
+    Graphics(Protocol),
+}
+
+static TUI_USAGE: &str = "
+Terminal options:
+  --braille               draw with braille dots instead of half blocks
+  --graphics[=<PROTOCOL>] draw real pixels with sixel or kitty graphics,
+                          detected from the terminal when not given
+";
+
+fn main() {
+    // the frontend's own options, the rest are shared with the SDL one
+    let mut output = Output::Text(TextMode::HalfBlock);
+    let mut args = Vec::new();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--braille" => output = Output::Text(TextMode::Braille),
+            // --graphics picks Sixel or Kitty from the terminal, or stays
+            // with text if neither looks supported
+            "--graphics" => match Protocol::detect() {
+                Some(protocol) => output = Output::Graphics(protocol),
+                None => eprintln!("No terminal graphics detected, using text"),
+            },
+            _ if arg.starts_with("--graphics=") => match Protocol::parse(&arg[11..]) {
+                Some(protocol) => output = Output::Graphics(protocol),
+                None => {
+                    eprintln!("error: unknown graphics protocol `{}`", &arg[11..]);
+                    process::exit(2);
+                }
+            },
+            _ => args.push(arg),
+        }
+    }
+
+    let mut options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {}\nTry `chip8-tui --help` for the options.", e);
+            process::exit(2);
+        }
+    };
+    if options.help {
+        print!(
+            "{}{}",
+            options::USAGE.replacen("chip8", "chip8-tui", 1),
+            TUI_USAGE
+        );
+        return;
+    }
+    if options.debug || options.headless {
+        eprintln!("error: --debug and --headless need the SDL frontend");
+        process::exit(2);
+    }
+
+    let rom = options.rom().to_string();
+    let (data, profile) = match options.read_rom() {
+        Ok(r) => (r.data, r.profile),
+        Err(e) => {
+            eprintln!("error: cannot read the ROM `{}`: {}", rom, e);
+            process::exit(2);
+        }
+    };
+    options.load_config(&data);
+    options.merge_octo_options(profile);
+
+    let mut cpu = Cpu::new();
+    if let Err(e) = options.load_rom(&mut cpu, &data) {
+        eprintln!("error: cannot load the ROM `{}`: {}", rom, e);
+        process::exit(2);
+    }
+
+    let palette = options.palette.clone().unwrap_or_default();
+    let config = options.keymap.as_ref().map_or("keymap.cfg", |k| k.as_str());
+    let mut keymap = Keymap::load_for_rom(config, &rom);
+    for &(button, ref keys) in &options.keys {
+        keymap.set(button, keys);
+    }
+
+    // the terminal has no symbols to show, like its coverage listing
+    let mut trace = match options.trace {
+        Some(ref path) => match File::create(path) {
+            Ok(f) => Some(BufWriter::new(f)),
+            Err(e) => {
+                eprintln!("error: cannot write `{}`: {}", path, e);
+                process::exit(2);
+            }
+        },
+        None => None,
+    };
+
+    // the terminal is put back however this ends, a panic included, which
+    // then gets its message shown on the normal screen
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        hook(info);
+    }));
+    let guard = TerminalGuard;
+
+    let mut stdout = io::stdout();
+    let result = terminal::enable_raw_mode()
+        .and_then(|_| {
+            execute!(
+                stdout,
+                terminal::EnterAlternateScreen,
+                cursor::Hide,
+                terminal::Clear(terminal::ClearType::All)
+            )
+        })
+        .and_then(|_| {
+            let trace = trace.as_mut().map(|t| t as &mut dyn Write);
+            run(
+                &mut cpu,
+                output,
+                &palette,
+                &keymap,
+                &options,
+                trace,
+                &mut stdout,
+            )
+        });
+
+    drop(guard);
+    if let Err(e) = result {
+        eprintln!("Error Writing Terminal {}", e);
+    }
+
+    if let Some(ref prefix) = options.coverage {
+        if let Err(e) = cpu.coverage.save(prefix, cpu.rom(), cpu.quirks(), None) {
+            eprintln!("Error Writing Coverage {}", e);
+        }
+    }
+
+    if let Some(e) = cpu.error() {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+// Restores the terminal when dropped.
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+// Leaves raw mode and the alternate screen, also when they were never
+// entered.
+fn restore_terminal() {
+    execute!(
+        io::stdout(),
+        ResetColor,
+        cursor::Show,
+        terminal::LeaveAlternateScreen
+    )
+    .ok();
+    terminal::disable_raw_mode().ok();
+}
+
+fn run<W: Write>(
+    cpu: &mut Cpu,
+    output: Output,
+    palette: &Palette,
+    keymap: &Keymap,
+    options: &Options,
+    mut trace: Option<&mut dyn Write>,
+    w: &mut W,
+) -> io::Result<()> {
+    let wait = Duration::from_nanos(1_000_000_000 / recorder::FPS);
+    let mut pressed: [Option<Instant>; 16] = [None; 16];
+    let mut sounding = false;
+    let mut control = Control::new();
+    let (fg, bg) = (palette.colour(1), palette.background());
+    let colours = SetColors(Colors::new(
+        Color::Rgb {
+            r: fg[0],
+            g: fg[1],
+            b: fg[2],
+        },
+        Color::Rgb {
+            r: bg[0],
+            g: bg[1],
+            b: bg[2],
+        },
+    ));
+
+    while options.frames.is_none_or(|n| cpu.frames() < n) {
+        let start = Instant::now();
+        while event::poll(Duration::from_secs(0))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Release {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Esc => return Ok(()),
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        return Ok(())
+                    }
+                    // the same hotkeys as the window, but Tab toggles fast
+                    // forward as there is no telling when it is let go
+                    KeyCode::F(3) => {
+                        control.toggle_pause();
+                    }
+                    KeyCode::Char('.') => control.advance_frame(),
+                    KeyCode::F(5) if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                        cpu.hard_reset()
+                    }
+                    KeyCode::F(5) => cpu.soft_reset(),
+                    KeyCode::Tab => {
+                        let on = !control.fast_forward();
+                        control.set_fast_forward(on);
+                    }
+                    KeyCode::Backspace => {
+                        let on = !control.slow_motion();
+                        control.set_slow_motion(on);
+                    }
+                    KeyCode::Char('-') => {
+                        control::slower(cpu);
+                    }
+                    KeyCode::Char('=') | KeyCode::Char('+') => {
+                        control::faster(cpu);
+                    }
+                    KeyCode::Char(c) => {
+                        if let Some(k) = keymap.button(&c.to_string()) {
+                            pressed[k] = Some(start);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for (key, p) in cpu.key.iter_mut().zip(pressed.iter()) {
+            *key = p.is_some_and(|t| start.duration_since(t) < KEY_HOLD) as u8;
+        }
+
+        // fast forward runs frames for as long as one normally takes
+        while control.run_frame() {
+            let frame = cpu.frames();
+            while cpu.frames() == frame {
+                if let Some(ref mut t) = trace {
+                    trace::write(t, cpu, None)?;
+                }
+                cpu.emulate_cycle();
+                // the program stopped, main says why
+                if cpu.error().is_some() {
+                    return Ok(());
+                }
+            }
+            let done = options.frames.is_some_and(|n| cpu.frames() >= n);
+            if done || !control.fast_forward() || start.elapsed() >= wait {
+                break;
+            }
+        }
+
+        // the terminal bell rings once each time the sound timer starts,
+        // unless the config turns the sound off
+        let beeping = cpu.sound_timer() > 0 && options.sound != Some(false);
+        if beeping && !sounding {
+            queue!(w, Print('\x07'))?;
+        }
+        sounding = beeping;
+
+        // the registers change all the time, so redraw every frame
+        let mut panel = tui::registers(cpu);
+        panel.push(String::new());
+        // always the same lines, so that old ones get written over
+        panel.push(format!("Speed {}", cpu.speed()));
+        panel.push(if control.paused() { "Paused" } else { "" }.to_string());
+        panel.push(
+            if control.fast_forward() {
+                "Fast forward"
+            } else if control.slow_motion() {
+                "Slow motion"
+            } else {
+                ""
+            }
+            .to_string(),
+        );
+        if let Some(ref info) = cpu.rom_info {
+            panel.push(String::new());
+            panel.extend(info.describe());
+        }
+        match output {
+            Output::Text(mode) => {
+                let screen = tui::render(&cpu.gpu, mode);
+                let columns = screen[0].chars().count();
+                for row in 0..screen.len().max(panel.len()) {
+                    queue!(w, cursor::MoveTo(0, row as u16))?;
+                    match screen.get(row) {
+                        Some(line) => queue!(w, colours, Print(line), ResetColor)?,
+                        None => queue!(w, Print(" ".repeat(columns)))?,
+                    }
+                }
+                draw_panel(&panel, columns as u16, w)?;
+            }
+            Output::Graphics(protocol) => {
+                // images are slow to send, only do it when they change
+                if cpu.gpu.draw_flag {
+                    queue!(w, cursor::MoveTo(0, 0))?;
+                    protocol.write(&cpu.gpu, GRAPHICS_SCALE, palette, w)?;
+                }
+                draw_panel(&panel, graphics_columns()?, w)?;
+            }
+        }
+        w.flush()?;
+        cpu.gpu.draw_flag = false;
+
+        //to keep a constant fps
+        let elapsed = start.elapsed();
+        let frame_time = control.frame_time();
+        if frame_time > elapsed {
+            thread::sleep(frame_time - elapsed);
+        }
+    }
+    Ok(())
+}
+
+// Draws the register panel to the right of the screen's first `columns`.
+fn draw_panel<W: Write>(panel: &[String], columns: u16, w: &mut W) -> io::Result<()> {
+    for (row, text) in panel.iter().enumerate() {
+        queue!(
+            w,
+            cursor::MoveTo(columns, row as u16),
+            Print(format!("  {:<20}", text))
+        )?;
+    }
+    Ok(())
+}
+
+// Columns covered by a graphics image, from the terminal's cell size.
+fn graphics_columns() -> io::Result<u16> {
+    let size = terminal::window_size()?;
+    // terminals that do not report pixels usually have cells about 8 wide
+    let cell = match size.width {
+        0 => 8,
+        width => (width / size.columns.max(1)).max(1) as usize,
+    };
+    Ok((WIDTH * GRAPHICS_SCALE).div_ceil(cell) as u16)
+}
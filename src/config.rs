@@ -0,0 +1,412 @@
+use palette::Palette;
+use quirks::{Platform, Quirks, NAMES};
+use sha1_smol::Sha1;
+use std::fs;
+use std::io;
+use std::path::Path;
+use toml_edit::{value, Array, DocumentMut, Item, Table, Value};
+
+// Settings for one ROM. Anything left as None or empty comes from
+// somewhere else: the command line, the defaults or the built-in values.
+#[derive(Clone, Default)]
+pub struct Profile {
+    pub platform: Option<Platform>,
+    // quirk overrides, in order
+    pub quirks: Vec<(String, bool)>,
+    pub speed: Option<u64>,
+    pub palette: Option<Palette>,
+    // keypad buttons and the keys that press them, as in keymap files
+    pub keys: Vec<(usize, Vec<String>)>,
+    pub sound: Option<bool>,
+    // whether the window title shows the ROM name
+    pub title: Option<bool>,
+}
+
+// A TOML file of default settings followed by per-ROM profiles, keyed by
+// the SHA-1 of the ROM, that take precedence over them:
+//
+//     platform = "vip"
+//     speed = 10
+//     palette = "amber"
+//
+//     [quirks]
+//     clip = false
+//
+//     [keys]
+//     5 = ["W", "Up"]
+//
+//     [audio]
+//     sound = false
+//
+//     [window]
+//     title = false
+//
+//     [launcher]
+//     folders = ["roms", "/usr/share/chip8"]
+//
+//     [rom.8e6dcb1f8b4a8b7c6f5a8d8e3b2d0b0f2d2b7a9c]
+//     name = "pong2.c8"
+//     speed = 7
+//
+// `name` is only there for people reading the file. Saving changes keeps
+// the rest of the file, comments included, as it was.
+pub struct Config {
+    doc: DocumentMut,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config {
+            doc: DocumentMut::new(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Config> {
+        let s = fs::read_to_string(path)?;
+        Config::parse(&s).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn parse(s: &str) -> Result<Config, String> {
+        let doc = s.parse::<DocumentMut>().map_err(|e| e.to_string())?;
+        Ok(Config { doc })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.doc.to_string())
+    }
+
+    // The defaults with the profile of the ROM with this hash on top.
+    pub fn profile(&self, hash: &str) -> Result<Profile, String> {
+        let mut profile = Profile::default();
+        read_table(self.doc.as_table(), &mut profile)?;
+
+        let rom = self
+            .doc
+            .get("rom")
+            .and_then(|r| r.as_table_like())
+            .and_then(|r| r.get(hash));
+        if let Some(table) = rom {
+            match table.as_table_like() {
+                Some(table) => {
+                    read_table(table, &mut profile).map_err(|e| format!("[rom.{}]: {}", hash, e))?
+                }
+                None => return Err(format!("rom.{} is not a table", hash)),
+            }
+        }
+
+        Ok(profile)
+    }
+
+    // Folders the launcher lists ROMs from, from `folders` in `[launcher]`.
+    pub fn folders(&self) -> Result<Vec<String>, String> {
+        let launcher = match self.doc.get("launcher") {
+            Some(launcher) => launcher.as_table_like().ok_or("launcher must be a table")?,
+            None => return Ok(Vec::new()),
+        };
+        let mut folders = Vec::new();
+        for (key, item) in launcher.iter() {
+            match key {
+                "folders" => match item.as_array() {
+                    Some(array) => folders = strings(array)?,
+                    None => return Err("folders must be a list".to_string()),
+                },
+                _ => return Err(format!("unknown launcher setting `{}`", key)),
+            }
+        }
+        Ok(folders)
+    }
+
+    // Writes the settings given in `profile` into the ROM's profile.
+    pub fn set_profile(&mut self, hash: &str, name: &str, profile: &Profile) {
+        let roms = self.doc.entry("rom").or_insert_with(|| {
+            let mut roms = Table::new();
+            roms.set_implicit(true);
+            Item::Table(roms)
+        });
+        let roms = match roms.as_table_mut() {
+            Some(roms) => roms,
+            None => return,
+        };
+        let table = roms
+            .entry(hash)
+            .or_insert_with(|| Item::Table(Table::new()))
+            .as_table_mut();
+        let table = match table {
+            Some(table) => table,
+            None => return,
+        };
+
+        table["name"] = value(name);
+        if let Some(platform) = profile.platform {
+            table["platform"] = value(platform.name());
+        }
+        if let Some(speed) = profile.speed {
+            table["speed"] = value(speed as i64);
+        }
+        if let Some(ref palette) = profile.palette {
+            table["palette"] = value(palette.to_string());
+        }
+        for &(ref name, on) in &profile.quirks {
+            table["quirks"].or_insert(Item::Table(Table::new()))[name.as_str()] = value(on);
+        }
+        for &(button, ref keys) in &profile.keys {
+            let keys: Array = keys.iter().map(|k| k.as_str()).collect();
+            table["keys"].or_insert(Item::Table(Table::new()))[&format!("{:X}", button)] =
+                value(keys);
+        }
+        if let Some(sound) = profile.sound {
+            table["audio"].or_insert(Item::Table(Table::new()))["sound"] = value(sound);
+        }
+        if let Some(title) = profile.title {
+            table["window"].or_insert(Item::Table(Table::new()))["title"] = value(title);
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config::new()
+    }
+}
+
+// Writes `profile` into the profile of the ROM `name` with the contents
+// `rom` in the config file at `path`, which is created if needed.
+pub fn save_profile<P: AsRef<Path>>(
+    path: P,
+    name: &str,
+    rom: &[u8],
+    profile: &Profile,
+) -> io::Result<()> {
+    let mut config = if path.as_ref().exists() {
+        Config::load(&path)?
+    } else {
+        Config::new()
+    };
+    config.set_profile(&rom_hash(rom), name, profile);
+    config.save(path)
+}
+
+// Hex SHA-1 of a ROM, which identifies it in profiles.
+pub fn rom_hash(data: &[u8]) -> String {
+    Sha1::from(data).digest().to_string()
+}
+
+// Every quirk as an override, to save all of them.
+pub fn quirk_list(quirks: &Quirks) -> Vec<(String, bool)> {
+    let values = [
+        quirks.shift,
+        quirks.load_store,
+        quirks.jump,
+        quirks.vf_reset,
+        quirks.clip,
+    ];
+    NAMES
+        .iter()
+        .zip(values.iter())
+        .map(|(name, &on)| (name.to_string(), on))
+        .collect()
+}
+
+// Reads the settings in a profile table into `profile`.
+pub fn read_table(table: &dyn toml_edit::TableLike, profile: &mut Profile) -> Result<(), String> {
+    for (key, item) in table.iter() {
+        match key {
+            "platform" => {
+                let name = item.as_str().ok_or("platform must be a string")?;
+                profile.platform = Some(
+                    Platform::parse(name).ok_or_else(|| format!("unknown platform `{}`", name))?,
+                );
+            }
+            "speed" => match item.as_integer() {
+                Some(speed) if speed > 0 && speed <= 1000 => profile.speed = Some(speed as u64),
+                _ => return Err("speed must be a number from 1 to 1000".to_string()),
+            },
+            "palette" => {
+                let spec = match item.as_value() {
+                    Some(Value::String(s)) => s.value().clone(),
+                    Some(Value::Array(colours)) => strings(colours)?.join(","),
+                    _ => return Err("palette must be a name or a list of colours".to_string()),
+                };
+                profile.palette = Some(Palette::parse(&spec)?);
+            }
+            "quirks" => {
+                let quirks = item.as_table_like().ok_or("quirks must be a table")?;
+                for (name, on) in quirks.iter() {
+                    let on = on
+                        .as_bool()
+                        .ok_or_else(|| format!("quirk `{}` must be true or false", name))?;
+                    Quirks::default().set(name, on)?;
+                    profile.quirks.push((name.to_string(), on));
+                }
+            }
+            "keys" => {
+                let keys = item.as_table_like().ok_or("keys must be a table")?;
+                for (button, k) in keys.iter() {
+                    let b = usize::from_str_radix(button, 16)
+                        .ok()
+                        .filter(|&b| b < 16)
+                        .ok_or_else(|| format!("unknown keypad button `{}`", button))?;
+                    let k = match k.as_value() {
+                        Some(Value::String(s)) => vec![s.value().clone()],
+                        Some(Value::Array(a)) => strings(a)?,
+                        _ => return Err(format!("keys for `{}` must be a list", button)),
+                    };
+                    profile.keys.push((b, k));
+                }
+            }
+            "audio" => {
+                let audio = item.as_table_like().ok_or("audio must be a table")?;
+                for (name, setting) in audio.iter() {
+                    match name {
+                        "sound" => {
+                            profile.sound =
+                                Some(setting.as_bool().ok_or("sound must be true or false")?)
+                        }
+                        _ => return Err(format!("unknown audio setting `{}`", name)),
+                    }
+                }
+            }
+            "window" => {
+                let window = item.as_table_like().ok_or("window must be a table")?;
+                for (name, setting) in window.iter() {
+                    match name {
+                        "title" => {
+                            profile.title =
+                                Some(setting.as_bool().ok_or("title must be true or false")?)
+                        }
+                        _ => return Err(format!("unknown window setting `{}`", name)),
+                    }
+                }
+            }
+            // profiles and the launcher are read separately, and names are
+            // for people
+            "rom" | "launcher" | "name" => {}
+            _ => return Err(format!("unknown setting `{}`", key)),
+        }
+    }
+    Ok(())
+}
+
+fn strings(array: &Array) -> Result<Vec<String>, String> {
+    array
+        .iter()
+        .map(|v| {
+            v.as_str()
+                .map(|s| s.to_string())
+                .ok_or_else(|| "expected a list of strings".to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static HASH: &str = "8e6dcb1f8b4a8b7c6f5a8d8e3b2d0b0f2d2b7a9c";
+
+    fn profile(s: &str) -> Result<Profile, String> {
+        Config::parse(s)?.profile(HASH)
+    }
+
+    #[test]
+    fn defaults_then_rom() {
+        let config = r##"
+platform = "vip"
+speed = 10
+palette = ["#000000", "#ffffff"]
+
+[quirks]
+clip = false
+
+[keys]
+5 = ["W", "Up"]
+a = "Z"
+
+[audio]
+sound = false
+
+[rom.8e6dcb1f8b4a8b7c6f5a8d8e3b2d0b0f2d2b7a9c]
+name = "pong2.c8"
+speed = 7
+quirks = { jump = true }
+
+[rom.0000000000000000000000000000000000000000]
+speed = 99
+"##;
+        let p = profile(config).unwrap();
+        assert_eq!(p.platform, Some(Platform::Vip));
+        assert_eq!(p.speed, Some(7));
+        assert_eq!(
+            p.quirks,
+            vec![("clip".to_string(), false), ("jump".to_string(), true)]
+        );
+        assert_eq!(
+            p.keys,
+            vec![
+                (5, vec!["W".to_string(), "Up".to_string()]),
+                (10, vec!["Z".to_string()])
+            ]
+        );
+        assert_eq!(p.sound, Some(false));
+        assert_eq!(
+            p.palette.unwrap().to_string(),
+            "#000000,#ffffff,#ffffff,#ffffff"
+        );
+
+        // another ROM only gets the defaults
+        let p = Config::parse(config).unwrap().profile("1234").unwrap();
+        assert_eq!(p.speed, Some(10));
+        assert_eq!(p.quirks, vec![("clip".to_string(), false)]);
+    }
+
+    #[test]
+    fn bad_values() {
+        for (config, error) in &[
+            ("platform = 3", "platform must be a string"),
+            ("platform = \"nes\"", "unknown platform `nes`"),
+            ("speed = 0", "speed must be a number from 1 to 1000"),
+            ("speed = \"fast\"", "speed must be a number from 1 to 1000"),
+            ("palette = 1", "palette must be a name or a list of colours"),
+            ("[quirks]\nclip = 1", "quirk `clip` must be true or false"),
+            ("[keys]\n10 = \"X\"", "unknown keypad button `10`"),
+            ("[audio]\nvolume = 3", "unknown audio setting `volume`"),
+            ("[window]\ntitle = \"no\"", "title must be true or false"),
+            ("colour = 1", "unknown setting `colour`"),
+        ] {
+            assert_eq!(profile(config).err().as_deref(), Some(*error));
+        }
+        assert_eq!(
+            profile("[rom.8e6dcb1f8b4a8b7c6f5a8d8e3b2d0b0f2d2b7a9c]\nspeed = 0")
+                .err()
+                .unwrap(),
+            format!("[rom.{}]: speed must be a number from 1 to 1000", HASH)
+        );
+        assert!(Config::parse("speed = ").is_err());
+    }
+
+    #[test]
+    fn saved_profiles_read_back() {
+        let mut config = Config::parse("# my settings\nspeed = 10\n").unwrap();
+        config.set_profile(
+            HASH,
+            "pong2.c8",
+            &Profile {
+                platform: Some(Platform::Schip),
+                quirks: vec![("shift".to_string(), false)],
+                speed: Some(12),
+                sound: Some(true),
+                title: Some(false),
+                ..Profile::default()
+            },
+        );
+        let saved = config.doc.to_string();
+        assert!(saved.starts_with("# my settings\nspeed = 10\n"));
+
+        let p = profile(&saved).unwrap();
+        assert_eq!(p.platform, Some(Platform::Schip));
+        assert_eq!(p.quirks, vec![("shift".to_string(), false)]);
+        assert_eq!(p.speed, Some(12));
+        assert_eq!(p.sound, Some(true));
+        assert_eq!(p.title, Some(false));
+    }
+}
@@ -0,0 +1,174 @@
+use cpu::Cpu;
+use recorder::FPS;
+use std::time::Duration;
+
+// The instructions per frame that faster and slower step through.
+pub static SPEEDS: [u64; 18] = [
+    1, 2, 3, 4, 5, 6, 7, 8, 10, 12, 15, 20, 30, 50, 100, 200, 500, 1000,
+];
+
+// How many times longer a frame takes in slow motion.
+pub static SLOW_MOTION: u32 = 4;
+
+// How a frontend runs the emulator: paused or not, and how fast. The Cpu
+// knows nothing of time, so this only tells the frontend when to run a
+// frame and how long to show it.
+#[derive(Default)]
+pub struct Control {
+    paused: bool,
+    // one frame to run while paused
+    advance: bool,
+    fast_forward: bool,
+    slow_motion: bool,
+}
+
+impl Control {
+    pub fn new() -> Control {
+        Control::default()
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.advance = false;
+    }
+
+    // Pauses or resumes, returning whether it is now paused.
+    pub fn toggle_pause(&mut self) -> bool {
+        let paused = !self.paused;
+        self.set_paused(paused);
+        paused
+    }
+
+    // Runs a single frame and stays paused, pausing first if need be.
+    pub fn advance_frame(&mut self) {
+        self.paused = true;
+        self.advance = true;
+    }
+
+    pub fn fast_forward(&self) -> bool {
+        self.fast_forward
+    }
+
+    // Runs frames as fast as the emulator can, with no waiting between them.
+    pub fn set_fast_forward(&mut self, on: bool) {
+        self.fast_forward = on;
+    }
+
+    pub fn slow_motion(&self) -> bool {
+        self.slow_motion
+    }
+
+    // Shows each frame SLOW_MOTION times as long.
+    pub fn set_slow_motion(&mut self, on: bool) {
+        self.slow_motion = on;
+    }
+
+    // Whether to run a frame now, using up a frame advance.
+    pub fn run_frame(&mut self) -> bool {
+        if !self.paused {
+            return true;
+        }
+        let advance = self.advance;
+        self.advance = false;
+        advance
+    }
+
+    // How long a frame should take: none when fast forwarding, 1/60 of a
+    // second otherwise, slowed down in slow motion.
+    pub fn frame_time(&self) -> Duration {
+        let frame = Duration::from_nanos(1_000_000_000 / FPS);
+        if self.fast_forward {
+            Duration::from_secs(0)
+        } else if self.slow_motion {
+            frame * SLOW_MOTION
+        } else {
+            frame
+        }
+    }
+}
+
+// Sets the Cpu to the next speed up in SPEEDS and returns it.
+pub fn faster(cpu: &mut Cpu) -> u64 {
+    let speed = SPEEDS
+        .iter()
+        .cloned()
+        .find(|&s| s > cpu.speed())
+        .unwrap_or(cpu.speed());
+    cpu.set_speed(speed);
+    speed
+}
+
+// Sets the Cpu to the next speed down in SPEEDS and returns it.
+pub fn slower(cpu: &mut Cpu) -> u64 {
+    let speed = SPEEDS
+        .iter()
+        .cloned()
+        .rev()
+        .find(|&s| s < cpu.speed())
+        .unwrap_or(cpu.speed());
+    cpu.set_speed(speed);
+    speed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pause_and_advance() {
+        let mut control = Control::new();
+        assert!(control.run_frame());
+
+        assert!(control.toggle_pause());
+        assert!(!control.run_frame());
+
+        // one frame at a time, staying paused
+        control.advance_frame();
+        assert!(control.run_frame());
+        assert!(!control.run_frame());
+        assert!(control.paused());
+
+        // advancing pauses a running game, resuming drops a pending advance
+        assert!(!control.toggle_pause());
+        control.advance_frame();
+        assert!(control.paused());
+        control.set_paused(false);
+        control.set_paused(true);
+        assert!(!control.run_frame());
+    }
+
+    #[test]
+    fn frame_times() {
+        let mut control = Control::new();
+        let frame = Duration::from_nanos(1_000_000_000 / FPS);
+        assert_eq!(control.frame_time(), frame);
+        control.set_slow_motion(true);
+        assert_eq!(control.frame_time(), frame * SLOW_MOTION);
+        // fast forward wins over slow motion
+        control.set_fast_forward(true);
+        assert_eq!(control.frame_time(), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn speed_steps() {
+        let mut cpu = Cpu::new();
+        assert_eq!(faster(&mut cpu), 6);
+        assert_eq!(slower(&mut cpu), 5);
+        assert_eq!(slower(&mut cpu), 4);
+
+        // between steps, then stopping at the ends
+        cpu.set_speed(9);
+        assert_eq!(faster(&mut cpu), 10);
+        cpu.set_speed(9);
+        assert_eq!(slower(&mut cpu), 8);
+        cpu.set_speed(1000);
+        assert_eq!(faster(&mut cpu), 1000);
+        cpu.set_speed(1);
+        assert_eq!(slower(&mut cpu), 1);
+        assert_eq!(cpu.speed(), 1);
+    }
+}
@@ -0,0 +1,385 @@
+use config;
+use disasm::{disassemble, target};
+use quirks::Quirks;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use symbols::Symbols;
+
+pub const EXECUTED: u8 = 0x1;
+pub const READ: u8 = 0x2;
+pub const WRITTEN: u8 = 0x4;
+
+// Maps from before the ROM's hash was in the header have the old magic
+// and are started over.
+static MAGIC: &[u8; 5] = b"C8CV2";
+// the SHA-1 of the ROM, in hex
+static HASH_SIZE: usize = 40;
+
+// Records, for every byte of memory, whether it was ever executed, read as
+// data or written by the running program.
+#[derive(Clone)]
+pub struct Coverage {
+    flags: [u8; 4096],
+    rom_start: usize,
+    rom_size: usize,
+    // which ROM the map is for, so that saves only merge runs of it
+    rom_hash: String,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage {
+            flags: [0; 4096],
+            rom_start: 0x200,
+            rom_size: 0,
+            rom_hash: String::new(),
+        }
+    }
+
+    // A different ROM starts the map over, so that what the last one did
+    // is not saved as this one's.
+    pub fn set_rom(&mut self, start: usize, rom: &[u8]) {
+        let hash = config::rom_hash(rom);
+        if hash != self.rom_hash {
+            self.flags = [0; 4096];
+        }
+        self.rom_start = start;
+        self.rom_size = rom.len();
+        self.rom_hash = hash;
+    }
+
+    pub fn rom_hash(&self) -> &str {
+        &self.rom_hash
+    }
+
+    pub fn mark(&mut self, addr: usize, len: usize, flag: u8) {
+        for a in addr..addr + len {
+            self.flags[a % 4096] |= flag;
+        }
+    }
+
+    pub fn flags(&self, addr: usize) -> u8 {
+        self.flags[addr % 4096]
+    }
+
+    // Adds the coverage of another run of the same ROM to this one; save
+    // checks that it is the same.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (a, b) in self.flags.iter_mut().zip(other.flags.iter()) {
+            *a |= *b;
+        }
+        self.rom_size = self.rom_size.max(other.rom_size);
+    }
+
+    // The raw map: a small header followed by one flag byte per address.
+    pub fn write_map<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[
+            (self.rom_start >> 8) as u8,
+            self.rom_start as u8,
+            (self.rom_size >> 8) as u8,
+            self.rom_size as u8,
+        ])?;
+        let mut hash = self.rom_hash.clone().into_bytes();
+        hash.resize(HASH_SIZE, b' ');
+        w.write_all(&hash)?;
+        w.write_all(&self.flags)
+    }
+
+    pub fn read_map<R: Read>(r: &mut R) -> io::Result<Coverage> {
+        let mut header = [0; 9 + HASH_SIZE];
+        r.read_exact(&mut header)?;
+        if &header[..5] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a coverage map",
+            ));
+        }
+
+        let mut coverage = Coverage::new();
+        coverage.rom_start = (header[5] as usize) << 8 | header[6] as usize;
+        coverage.rom_size = (header[7] as usize) << 8 | header[8] as usize;
+        coverage.rom_hash = String::from_utf8_lossy(&header[9..]).trim_end().to_string();
+        r.read_exact(&mut coverage.flags)?;
+
+        Ok(coverage)
+    }
+
+    // Annotated disassembly of `rom` as it was loaded, not as the program
+    // may have rewritten it since. Executed addresses are decoded as
+    // instructions, everything else is listed byte by byte as data. With
+    // symbols, every named or referenced address gets a label line.
+    pub fn write_listing<W: Write>(
+        &self,
+        rom: &[u8],
+        quirks: &Quirks,
+        symbols: Option<&Symbols>,
+        w: &mut W,
+    ) -> io::Result<()> {
+        let rom = &rom[..rom.len().min(self.rom_size)];
+        let end = self.rom_start + rom.len();
+        let byte = |a: usize| rom[a - self.rom_start];
+        let opcode = |a: usize| (byte(a) as u16) << 8 | byte(a + 1) as u16;
+        let mut addr = self.rom_start;
+
+        let mut targets = BTreeSet::new();
+        if symbols.is_some() {
+            for a in self.rom_start..end {
+                if self.flags(a) & EXECUTED != 0 && a + 1 < end {
+                    targets.extend(target(opcode(a)));
+                }
+            }
+        }
+
+        while addr < end {
+            if let Some(symbols) = symbols {
+                match symbols.get(addr) {
+                    Some(s) => match (&s.file, s.line) {
+                        (Some(file), Some(line)) => {
+                            writeln!(w, "{}:  ; {}:{}", s.name, file, line)?
+                        }
+                        (Some(file), None) => writeln!(w, "{}:  ; {}", s.name, file)?,
+                        _ => writeln!(w, "{}:", s.name)?,
+                    },
+                    None if targets.contains(&addr) => writeln!(w, "{}:", symbols.label(addr))?,
+                    None => {}
+                }
+            }
+
+            let flags = self.flags(addr);
+            if flags & EXECUTED != 0 && addr + 1 < end {
+                let opcode = opcode(addr);
+                writeln!(
+                    w,
+                    "{:03X}  {:04X}  {}  {}",
+                    addr,
+                    opcode,
+                    flag_string(flags | self.flags(addr + 1)),
+                    disassemble(opcode, quirks, symbols)
+                )?;
+                addr += 2;
+            } else {
+                writeln!(
+                    w,
+                    "{:03X}  {:02X}    {}  db {:#04X}",
+                    addr,
+                    byte(addr),
+                    flag_string(flags),
+                    byte(addr)
+                )?;
+                addr += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        let rom = self.rom_start..self.rom_start + self.rom_size;
+        let count = |flag: u8| rom.clone().filter(|&a| self.flags(a) & flag != 0).count();
+        let unused = rom.clone().filter(|&a| self.flags(a) == 0).count();
+
+        format!(
+            "{{\"rom_start\":{},\"rom_size\":{},\"executed\":{},\"read\":{},\"written\":{},\"unused\":{}}}",
+            self.rom_start,
+            self.rom_size,
+            self.json_section(count(EXECUTED), |f| f & EXECUTED != 0),
+            self.json_section(count(READ), |f| f & READ != 0),
+            self.json_section(count(WRITTEN), |f| f & WRITTEN != 0),
+            self.json_section(unused, |f| f == 0),
+        )
+    }
+
+    // Writes `<prefix>.cov`, `<prefix>.json` and `<prefix>.lst`, merging with
+    // the map of earlier runs when one is already there. A map of another
+    // ROM, or one that cannot be read, is started over. `rom` is the ROM as
+    // it was loaded, for the listing.
+    pub fn save(
+        &self,
+        prefix: &str,
+        rom: &[u8],
+        quirks: &Quirks,
+        symbols: Option<&Symbols>,
+    ) -> io::Result<()> {
+        let map_path = format!("{}.cov", prefix);
+        let mut total = self.clone();
+        if let Ok(mut f) = File::open(&map_path) {
+            match Coverage::read_map(&mut f) {
+                Ok(ref old) if old.rom_hash == self.rom_hash => total.merge(old),
+                Ok(_) => eprintln!("{} is for another ROM, starting it over", map_path),
+                Err(e) => eprintln!("Error Reading {}: {}, starting it over", map_path, e),
+            }
+        }
+
+        total.write_map(&mut File::create(&map_path)?)?;
+        File::create(format!("{}.json", prefix))?.write_all(total.to_json().as_bytes())?;
+        total.write_listing(
+            rom,
+            quirks,
+            symbols,
+            &mut File::create(format!("{}.lst", prefix))?,
+        )
+    }
+
+    fn json_section<F: Fn(u8) -> bool>(&self, bytes: usize, pred: F) -> String {
+        let ranges: Vec<String> = self
+            .ranges(pred)
+            .iter()
+            .map(|&(start, end)| format!("{{\"start\":{},\"end\":{}}}", start, end))
+            .collect();
+        format!("{{\"bytes\":{},\"ranges\":[{}]}}", bytes, ranges.join(","))
+    }
+
+    // Contiguous runs of the ROM whose flags match, as half-open ranges.
+    fn ranges<F: Fn(u8) -> bool>(&self, pred: F) -> Vec<(usize, usize)> {
+        let mut ranges = Vec::new();
+        let mut start = None;
+
+        for a in self.rom_start..self.rom_start + self.rom_size {
+            match (pred(self.flags(a)), start) {
+                (true, None) => start = Some(a),
+                (false, Some(s)) => {
+                    ranges.push((s, a));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(s) = start {
+            ranges.push((s, self.rom_start + self.rom_size));
+        }
+
+        ranges
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Coverage {
+        Coverage::new()
+    }
+}
+
+fn flag_string(flags: u8) -> String {
+    let mut s = String::new();
+    s.push(if flags & EXECUTED != 0 { 'X' } else { '.' });
+    s.push(if flags & READ != 0 { 'R' } else { '.' });
+    s.push(if flags & WRITTEN != 0 { 'W' } else { '.' });
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quirks::Platform;
+    use std::env;
+    use std::fs;
+
+    static ROM: [u8; 5] = [0x60, 0x05, 0x12, 0x00, 0xAB];
+
+    // LD V0, 5 and JP 0x200 run, the byte after them read
+    fn run(rom: &[u8]) -> Coverage {
+        let mut coverage = Coverage::new();
+        coverage.set_rom(0x200, rom);
+        coverage.mark(0x200, 4, EXECUTED);
+        coverage.mark(0x204, 1, READ);
+        coverage
+    }
+
+    #[test]
+    fn merge_keeps_the_flags_of_both() {
+        let mut a = Coverage::new();
+        a.mark(0x200, 2, EXECUTED);
+        let mut b = Coverage::new();
+        b.mark(0x200, 1, WRITTEN);
+        b.mark(0x300, 1, READ);
+        a.merge(&b);
+        assert_eq!(a.flags(0x200), EXECUTED | WRITTEN);
+        assert_eq!(a.flags(0x201), EXECUTED);
+        assert_eq!(a.flags(0x300), READ);
+    }
+
+    #[test]
+    fn another_rom_starts_over() {
+        let mut coverage = run(&ROM);
+        coverage.set_rom(0x200, &ROM);
+        assert_eq!(coverage.flags(0x200), EXECUTED);
+
+        coverage.set_rom(0x200, &[0x00, 0xE0]);
+        assert_eq!(coverage.flags(0x200), 0);
+        assert_eq!(coverage.flags(0x204), 0);
+    }
+
+    #[test]
+    fn map_round_trip() {
+        let coverage = run(&ROM);
+        let mut map = Vec::new();
+        coverage.write_map(&mut map).unwrap();
+        let read = Coverage::read_map(&mut &map[..]).unwrap();
+        assert_eq!(read.rom_start, 0x200);
+        assert_eq!(read.rom_size, 5);
+        assert_eq!(read.rom_hash(), config::rom_hash(&ROM));
+        assert_eq!(&read.flags[..], &coverage.flags[..]);
+
+        assert!(Coverage::read_map(&mut &b"C8COV\0\0\0\0"[..]).is_err());
+    }
+
+    #[test]
+    fn json_summary() {
+        let coverage = run(&ROM);
+        assert_eq!(
+            coverage.to_json(),
+            "{\"rom_start\":512,\"rom_size\":5,\
+             \"executed\":{\"bytes\":4,\"ranges\":[{\"start\":512,\"end\":516}]},\
+             \"read\":{\"bytes\":1,\"ranges\":[{\"start\":516,\"end\":517}]},\
+             \"written\":{\"bytes\":0,\"ranges\":[]},\
+             \"unused\":{\"bytes\":0,\"ranges\":[]}}"
+        );
+    }
+
+    #[test]
+    fn listing() {
+        let coverage = run(&ROM);
+        let mut listing = Vec::new();
+        let quirks = Platform::Modern.quirks();
+        coverage
+            .write_listing(&ROM, &quirks, None, &mut listing)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(listing).unwrap(),
+            "200  6005  X..  LD V0, 0x05\n\
+             202  1200  X..  JP 0x200\n\
+             204  AB    .R.  db 0xAB\n"
+        );
+    }
+
+    #[test]
+    fn save_merges_runs_of_the_same_rom_only() {
+        let dir = env::temp_dir().join(format!("chip8-coverage-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let prefix = dir.join("run").to_string_lossy().into_owned();
+        let read = || Coverage::read_map(&mut File::open(format!("{}.cov", prefix)).unwrap());
+        let quirks = Platform::Modern.quirks();
+
+        let first = run(&ROM);
+        first.save(&prefix, &ROM, &quirks, None).unwrap();
+        let mut second = Coverage::new();
+        second.set_rom(0x200, &ROM);
+        second.mark(0x204, 1, WRITTEN);
+        second.save(&prefix, &ROM, &quirks, None).unwrap();
+        assert_eq!(read().unwrap().flags(0x204), READ | WRITTEN);
+        assert_eq!(read().unwrap().flags(0x200), EXECUTED);
+
+        // another ROM under the same prefix starts over
+        let mut other = Coverage::new();
+        other.set_rom(0x200, &[0x00, 0xE0]);
+        other.mark(0x200, 2, EXECUTED);
+        other.save(&prefix, &[0x00, 0xE0], &quirks, None).unwrap();
+        let map = read().unwrap();
+        assert_eq!(map.rom_hash(), config::rom_hash(&[0x00, 0xE0]));
+        assert_eq!(map.flags(0x204), 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
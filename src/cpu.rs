@@ -1,10 +1,25 @@
+use config;
+use coverage::{Coverage, EXECUTED, READ, WRITTEN};
 use gpu::Gpu;
-use rand::{thread_rng, Rng};
-use sdl2;
-use std::fs::File;
-use std::io::prelude::*;
-use std::process;
-
+use patch;
+use quirks::Quirks;
+use rand::prng::XorShiftRng;
+use rand::{thread_rng, Rng, SeedableRng};
+use romdb::{RomDb, RomInfo};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+// Instructions per frame by default, 300 a second at 60 frames a second.
+pub static SPEED: u64 = 5;
+
+// ROMs go after the 512 bytes the interpreter took up on the COSMAC VIP.
+pub static LOAD_ADDRESS: usize = 0x200;
+pub static MEMORY_SIZE: usize = 4096;
+
+#[derive(Clone)]
 pub struct Cpu {
     opcode: u16,
     memory: [u8; 4096],
@@ -23,36 +38,93 @@ pub struct Cpu {
 
     //display
     pub gpu: Gpu,
+
+    pub coverage: Coverage,
+
+    quirks: Quirks,
+    // whether set_quirks and set_speed were used, so that the ROM database
+    // leaves what the caller chose alone
+    quirks_set: bool,
+    speed_set: bool,
+
+    // what the ROM database knows about the loaded game
+    pub rom_info: Option<RomInfo>,
+    // the instruction the program stopped on, until a reset
+    error: Option<UnknownOpcode>,
+    load_address: usize,
+    // the ROM as loaded, for resets
+    rom: Vec<u8>,
+
+    // everything that makes a run reproducible lives in the Cpu, so a clone
+    // of it can be replayed to the exact same state
+    rng: XorShiftRng,
+    seed: Option<u64>,
+    cycles: u64,
+    // instructions per frame, and the frame count the timers follow
+    speed: u64,
+    frame_cycles: u64,
+    frames: u64,
 }
 
 impl Cpu {
-    pub fn new(_sdlcontext: &sdl2::Sdl) -> Cpu {
+    pub fn new() -> Cpu {
         let mut cpu = Cpu {
             opcode: 0,
             memory: [0; 4096],
             v: [0; 16],
             i: 0,
-            pc: 0x200,
+            pc: LOAD_ADDRESS,
             stack: [0; 16],
             sp: 0,
             dt: 0,
             st: 0,
             key: [0; 16],
-            gpu: Gpu::new(_sdlcontext),
+            gpu: Gpu::new(),
+            coverage: Coverage::new(),
+            quirks: Quirks::default(),
+            quirks_set: false,
+            speed_set: false,
+            rom_info: None,
+            error: None,
+            load_address: LOAD_ADDRESS,
+            rom: Vec::new(),
+            rng: XorShiftRng::from_rng(thread_rng()).unwrap(),
+            seed: None,
+            cycles: 0,
+            speed: SPEED,
+            frame_cycles: 0,
+            frames: 0,
         };
 
         //fill the fontset
-        for j in 0..80 {
-            cpu.memory[j] = FONTSET[j];
-        }
+        cpu.memory[..80].copy_from_slice(&FONTSET);
 
         cpu
     }
 
+    // Runs one instruction. After an unknown one this does nothing until a
+    // reset, and `error` says what it was.
     pub fn emulate_cycle(&mut self) {
+        if self.error.is_some() {
+            return;
+        }
+
         self.fetch_opcode();
 
         self.execute_opcode();
+        if self.error.is_some() {
+            return;
+        }
+
+        self.cycles += 1;
+
+        // a frame ends every `speed` instructions, the timers count frames
+        self.frame_cycles += 1;
+        if self.frame_cycles < self.speed {
+            return;
+        }
+        self.frame_cycles = 0;
+        self.frames += 1;
 
         // Update timers
         if self.dt > 0 {
@@ -69,6 +141,7 @@ impl Cpu {
 
     pub fn fetch_opcode(&mut self) {
         self.opcode = (self.memory[self.pc] as u16) << 8 | (self.memory[self.pc + 1] as u16);
+        self.coverage.mark(self.pc, 2, EXECUTED);
     }
 
     pub fn execute_opcode(&mut self) {
@@ -92,10 +165,7 @@ impl Cpu {
                     self.pc = self.stack[self.sp] as usize;
                     self.pc += 2;
                 }
-                _ => {
-                    println!("Not Implemented {:x?}", self.opcode);
-                    process::exit(0x0100);
-                }
+                _ => self.unknown_opcode(),
             },
 
             0x1000 => {
@@ -164,16 +234,19 @@ impl Cpu {
                     0x0001 => {
                         // Set Vx = Vx OR Vy
                         self.v[self.op_x()] |= self.v[self.op_y()];
+                        self.vf_reset();
                     }
 
                     0x0002 => {
                         // Set Vx = Vx AND Vy
                         self.v[self.op_x()] &= self.v[self.op_y()];
+                        self.vf_reset();
                     }
 
                     0x0003 => {
                         // Set Vx = Vx XOR Vy
                         self.v[self.op_x()] ^= self.v[self.op_y()];
+                        self.vf_reset();
                     }
                     0x0004 => {
                         // Set Vx = Vx + Vy, set VF = carry.
@@ -192,8 +265,9 @@ impl Cpu {
                     }
 
                     0x0006 => {
-                        self.v[15] = self.v[self.op_x()] & 0x1;
-                        self.v[self.op_x()] >>= 1;
+                        let src = self.shift_source();
+                        self.v[15] = src & 0x1;
+                        self.v[self.op_x()] = src >> 1;
                     }
                     0x0007 => {
                         self.v[15] = if self.v[self.op_x()] > self.v[self.op_y()] {
@@ -204,14 +278,12 @@ impl Cpu {
                         self.v[self.op_x()] = self.v[self.op_y()] - self.v[self.op_x()];
                     }
                     0x000E => {
-                        self.v[15] = self.v[self.op_x()] >> 7;
-                        self.v[self.op_x()] <<= 1;
+                        let src = self.shift_source();
+                        self.v[15] = src >> 7;
+                        self.v[self.op_x()] = src << 1;
                     }
 
-                    _ => {
-                        println!("Not Implemented {:x?}", self.opcode);
-                        process::exit(0x0100);
-                    }
+                    _ => return self.unknown_opcode(),
                 }
                 self.pc += 2;
             }
@@ -230,13 +302,14 @@ impl Cpu {
             }
 
             0xB000 => {
-                // Jump to location nnn + V0
-                self.pc = ((self.opcode & 0x0FFF) + self.v[0] as u16) as usize;
+                // Jump to location nnn + V0, or nnn + Vx with the jump quirk
+                let v = if self.quirks.jump { self.op_x() } else { 0 };
+                self.pc = ((self.opcode & 0x0FFF) + self.v[v] as u16) as usize;
             }
 
             0xC000 => {
                 //RND Vx, byte
-                let random_number: u8 = thread_rng().gen();
+                let random_number: u8 = self.rng.gen();
                 self.v[self.op_x()] = (self.opcode & 0x00FF) as u8 & random_number;
                 self.pc += 2;
             }
@@ -247,11 +320,13 @@ impl Cpu {
                 let x = self.v[self.op_x()];
                 let y = self.v[self.op_y()];
 
-                self.v[15] = self.gpu.draw_sprite(
-                    x as usize,
-                    y as usize,
-                    &self.memory[self.i..(self.i + (size as usize))],
-                );
+                self.coverage.mark(self.i, size as usize, READ);
+                let sprite = &self.memory[self.i..(self.i + (size as usize))];
+                self.v[15] = if self.quirks.clip {
+                    self.gpu.draw_sprite_clipped(x as usize, y as usize, sprite)
+                } else {
+                    self.gpu.draw_sprite(x as usize, y as usize, sprite)
+                };
                 self.pc += 2;
             }
 
@@ -273,10 +348,7 @@ impl Cpu {
                         self.pc += 2;
                     }
 
-                    _ => {
-                        println!("Not Implemented {:x?}", self.opcode);
-                        process::exit(0x0100);
-                    }
+                    _ => self.unknown_opcode(),
                 }
             }
 
@@ -288,16 +360,12 @@ impl Cpu {
                 }
 
                 0x000A => {
-                    // Wait for a key press, store the value of the key in Vx
-                    'wait_key: loop {
-                        for i in 0..15 {
-                            if self.key[i] == 1 {
-                                self.v[self.op_x()] = i as u8;
-                                break 'wait_key;
-                            }
-                        }
+                    // Wait for a key press, store the value of the key in Vx.
+                    // Without a key the instruction runs again next cycle.
+                    if let Some(k) = self.key.iter().position(|&k| k == 1) {
+                        self.v[self.op_x()] = k as u8;
+                        self.pc += 2;
                     }
-                    self.pc += 2;
                 }
 
                 0x0015 => {
@@ -330,6 +398,7 @@ impl Cpu {
                     self.memory[self.i] = val / 100;
                     self.memory[self.i + 1] = val / 10 % 10;
                     self.memory[self.i + 2] = val % 100 % 10;
+                    self.coverage.mark(self.i, 3, WRITTEN);
 
                     self.pc += 2;
                 }
@@ -339,6 +408,10 @@ impl Cpu {
                     for i in 0..(self.op_x() + 1) {
                         self.memory[self.i + i] = self.v[i];
                     }
+                    self.coverage.mark(self.i, self.op_x() + 1, WRITTEN);
+                    if !self.quirks.load_store {
+                        self.i += self.op_x() + 1;
+                    }
                     self.pc += 2;
                 }
 
@@ -349,22 +422,27 @@ impl Cpu {
                     for i in 0..(n + 1) {
                         self.v[i] = self.memory[self.i + i];
                     }
+                    self.coverage.mark(self.i, n + 1, READ);
+                    if !self.quirks.load_store {
+                        self.i += n + 1;
+                    }
                     self.pc += 2;
                 }
 
-                _ => {
-                    println!("Not Implemented {:x?}", self.opcode);
-                    process::exit(0x0100);
-                }
+                _ => self.unknown_opcode(),
             },
 
-            _ => {
-                println!("Not Implemented {:x?}", self.opcode);
-                process::exit(0x0100);
-            }
+            _ => self.unknown_opcode(),
         }
     }
 
+    fn unknown_opcode(&mut self) {
+        self.error = Some(UnknownOpcode {
+            opcode: self.opcode,
+            address: self.pc,
+        });
+    }
+
     fn op_x(&self) -> usize {
         ((self.opcode & 0x0F00) >> 8) as usize
     }
@@ -372,23 +450,312 @@ impl Cpu {
         ((self.opcode & 0x00F0) >> 4) as usize
     }
 
-    pub fn load_game(&mut self, s: String) {
-        let mut f = File::open(s).unwrap();
-        let mut buffer = Vec::new();
+    // the register 8XY6 and 8XYE shift
+    fn shift_source(&self) -> u8 {
+        if self.quirks.shift {
+            self.v[self.op_x()]
+        } else {
+            self.v[self.op_y()]
+        }
+    }
+
+    fn vf_reset(&mut self) {
+        if self.quirks.vf_reset {
+            self.v[15] = 0;
+        }
+    }
+
+    // Instructions per frame; the timers and `frames` move on once per
+    // frame.
+    pub fn set_speed(&mut self, speed: u64) {
+        self.speed = speed.max(1);
+        self.speed_set = true;
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.quirks_set = true;
+    }
+
+    pub fn speed(&self) -> u64 {
+        self.speed
+    }
 
-        match f.read_to_end(&mut buffer) {
-            Ok(result) => {
-                println!("Read {} bytes.", result);
-                //fill memory with the game
-                for i in 0..result {
-                    self.memory[self.pc] = buffer[i];
-                    self.pc += 1;
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // Makes RND give the same numbers on every run.
+    pub fn set_seed(&mut self, seed: u64) {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&seed.to_le_bytes());
+        bytes[8..].copy_from_slice(&(seed ^ 0x9E37_79B9_7F4A_7C15).to_le_bytes());
+        self.rng = XorShiftRng::from_seed(bytes);
+        self.seed = Some(seed);
+    }
+
+    // Starts the loaded ROM over, like the reset switch: the ROM is copied
+    // in again over whatever it changed, and the registers, stack, timers,
+    // keys and screen are cleared. The quirks, speed and the cycle and frame
+    // counts stay.
+    pub fn soft_reset(&mut self) {
+        let start = self.load_address;
+        for b in self.memory[start..].iter_mut() {
+            *b = 0;
+        }
+        self.memory[start..start + self.rom.len()].copy_from_slice(&self.rom);
+        self.opcode = 0;
+        self.v = [0; 16];
+        self.i = 0;
+        self.pc = start;
+        self.sp = 0;
+        self.stack = [0; 16];
+        self.dt = 0;
+        self.st = 0;
+        self.key = [0; 16];
+        self.frame_cycles = 0;
+        self.error = None;
+        self.gpu.clear_screen();
+        self.gpu.draw_flag = true;
+    }
+
+    // Like turning the machine off and on: a soft reset that also clears
+    // the memory below the ROM, puts the font back and reseeds the random
+    // numbers, with the same seed if one was set.
+    pub fn hard_reset(&mut self) {
+        for b in self.memory[..self.load_address].iter_mut() {
+            *b = 0;
+        }
+        self.memory[..80].copy_from_slice(&FONTSET);
+        match self.seed {
+            Some(seed) => self.set_seed(seed),
+            None => self.rng = XorShiftRng::from_rng(thread_rng()).unwrap(),
+        }
+        self.soft_reset();
+    }
+
+    // The unknown instruction that stopped the program, if any.
+    pub fn error(&self) -> Option<UnknownOpcode> {
+        self.error
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn v(&self) -> &[u8; 16] {
+        &self.v
+    }
+
+    pub fn i(&self) -> usize {
+        self.i
+    }
+
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.dt
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.st
+    }
+
+    pub fn registers(&self) -> String {
+        let mut s = String::new();
+        for (i, v) in self.v.iter().enumerate() {
+            s.push_str(&format!("v{:x}: {:02x} | ", i, v));
+        }
+        s.push_str(&format!(
+            "\ni: {:03x} | pc: {:03x} | sp: {:x} | dt: {:02x} | st: {:02x} | cycle: {}",
+            self.i, self.pc, self.sp, self.dt, self.st, self.cycles
+        ));
+        s
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    // The ROM as it was loaded, whatever the program wrote over it since.
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    pub fn load_address(&self) -> usize {
+        self.load_address
+    }
+
+    // Where the next ROM goes and the program starts, 0x200 unless the ROM
+    // was written for an interpreter that loads them elsewhere, like 0x600
+    // on the ETI-660. The first opcode must fit below the end of memory.
+    pub fn set_load_address(&mut self, address: usize) -> Result<(), LoadError> {
+        if address < LOAD_ADDRESS || address + 2 > MEMORY_SIZE {
+            return Err(LoadError::BadAddress(address));
+        }
+        // the ROM already loaded has to fit for resets
+        if self.rom.len() > MEMORY_SIZE - address {
+            return Err(LoadError::TooLarge {
+                size: self.rom.len(),
+                available: MEMORY_SIZE - address,
+            });
+        }
+        self.load_address = address;
+        self.pc = address;
+        Ok(())
+    }
+
+    // Loads a game and takes the quirks and speed it needs from the built-in
+    // ROM database, unless they were set before.
+    pub fn load_game<P: AsRef<Path>>(&mut self, path: P) -> Result<(), LoadError> {
+        let data = fs::read(path)?;
+        self.load_bytes(&data)
+    }
+
+    // Loads a game with IPS or BPS patches applied to it, in order.
+    pub fn load_game_patched<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        path: P,
+        patches: &[Q],
+    ) -> Result<(), LoadError> {
+        let mut data = fs::read(path)?;
+        for patch in patches {
+            data = patch::apply_file(&data, patch).map_err(LoadError::Patch)?;
+        }
+        self.load_bytes(&data)
+    }
+
+    pub fn load_reader<R: Read>(&mut self, mut reader: R) -> Result<(), LoadError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        self.load_bytes(&data)
+    }
+
+    pub fn load_bytes(&mut self, data: &[u8]) -> Result<(), LoadError> {
+        self.load_bytes_with(data, &RomDb::builtin())
+    }
+
+    // Like load_bytes, with `db` as the ROM database.
+    pub fn load_bytes_with(&mut self, data: &[u8], db: &RomDb) -> Result<(), LoadError> {
+        let start = self.load_address;
+        if data.is_empty() {
+            return Err(LoadError::Empty);
+        }
+        if data.len() > MEMORY_SIZE - start {
+            return Err(LoadError::TooLarge {
+                size: data.len(),
+                available: MEMORY_SIZE - start,
+            });
+        }
+
+        //fill memory with the game, clearing what a previous one left
+        for b in self.memory[start..].iter_mut() {
+            *b = 0;
+        }
+        self.memory[start..start + data.len()].copy_from_slice(data);
+        self.rom = data.to_vec();
+        self.coverage.set_rom(start, data);
+        self.pc = start;
+
+        self.rom_info = db.get(&config::rom_hash(data)).cloned();
+        if let Some(ref info) = self.rom_info {
+            let profile = &info.profile;
+            if !self.quirks_set {
+                if let Some(platform) = profile.platform {
+                    self.quirks = platform.quirks();
+                }
+                for &(ref name, on) in &profile.quirks {
+                    self.quirks.set(name, on).ok();
                 }
             }
-            Err(e) => println!("Error Reading {}", e),
+            if !self.speed_set {
+                if let Some(speed) = profile.speed {
+                    self.speed = speed;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Cpu {
+    fn default() -> Cpu {
+        Cpu::new()
+    }
+}
+
+// Why a ROM could not be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Empty,
+    // the ROM does not fit between the load address and the end of memory
+    TooLarge { size: usize, available: usize },
+    // load addresses go from 0x200 to the last opcode in memory
+    BadAddress(usize),
+    // a zip archive or cartridge without a ROM that can be loaded
+    Format(String),
+    // a patch that could not be applied
+    Patch(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Io(ref e) => write!(f, "{}", e),
+            LoadError::Empty => write!(f, "the ROM is empty"),
+            LoadError::TooLarge { size, available } => write!(
+                f,
+                "the ROM is {} bytes, but only {} fit in memory",
+                size, available
+            ),
+            LoadError::BadAddress(address) => write!(
+                f,
+                "cannot load at {:#X}, the load address must be from {:#X} to {:#X}",
+                address,
+                LOAD_ADDRESS,
+                MEMORY_SIZE - 2
+            ),
+            LoadError::Format(ref e) => write!(f, "{}", e),
+            LoadError::Patch(ref e) => write!(f, "{}", e),
         }
+    }
+}
+
+impl error::Error for LoadError {}
 
-        self.pc = 0x200;
+// An instruction the Cpu does not know, and where it was.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UnknownOpcode {
+    pub opcode: u16,
+    pub address: usize,
+}
+
+impl fmt::Display for UnknownOpcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown opcode {:04X} at {:03X}",
+            self.opcode, self.address
+        )
+    }
+}
+
+impl error::Error for UnknownOpcode {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> LoadError {
+        LoadError::Io(e)
     }
 }
 
@@ -399,3 +766,127 @@ static FONTSET: [u8; 80] = [
     0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80,
     0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quirks::Platform;
+
+    #[test]
+    fn load_errors() {
+        let mut cpu = Cpu::new();
+        match cpu.load_bytes(&[]) {
+            Err(LoadError::Empty) => {}
+            _ => panic!("an empty ROM loaded"),
+        }
+        match cpu.load_bytes(&vec![0; MEMORY_SIZE - LOAD_ADDRESS + 1]) {
+            Err(LoadError::TooLarge { size, available }) => {
+                assert_eq!((size, available), (0xE01, 0xE00))
+            }
+            _ => panic!("a ROM too large loaded"),
+        }
+        for &address in &[0x1FF, MEMORY_SIZE - 1] {
+            match cpu.set_load_address(address) {
+                Err(LoadError::BadAddress(a)) => assert_eq!(a, address),
+                _ => panic!("loading at {:#X}", address),
+            }
+        }
+
+        // the largest ROM that fits
+        cpu.load_bytes(&vec![0; MEMORY_SIZE - LOAD_ADDRESS]).unwrap();
+        assert_eq!(cpu.rom().len(), 0xE00);
+    }
+
+    #[test]
+    fn load_address_fits_the_rom() {
+        let mut cpu = Cpu::new();
+        cpu.load_bytes(&[0xAA; 0x200]).unwrap();
+        match cpu.set_load_address(0xF00) {
+            Err(LoadError::TooLarge { size, available }) => {
+                assert_eq!((size, available), (0x200, 0x100))
+            }
+            _ => panic!("moved the ROM past the end of memory"),
+        }
+        cpu.soft_reset();
+        assert_eq!(cpu.pc(), 0x200);
+
+        cpu.set_load_address(0xE00).unwrap();
+        cpu.soft_reset();
+        assert_eq!(cpu.pc(), 0xE00);
+        assert_eq!(cpu.memory()[0xFFF], 0xAA);
+    }
+
+    #[test]
+    fn load_from_a_reader() {
+        let mut cpu = Cpu::new();
+        cpu.set_load_address(0x600).unwrap();
+        cpu.load_reader(&[0x60, 0x2A][..]).unwrap();
+        assert_eq!(cpu.pc(), 0x600);
+        assert_eq!(cpu.rom(), &[0x60, 0x2A]);
+        assert_eq!(&cpu.memory()[0x600..0x602], &[0x60, 0x2A]);
+        cpu.emulate_cycle();
+        assert_eq!(cpu.v()[0], 0x2A);
+    }
+
+    #[test]
+    fn unknown_opcodes_stop() {
+        let mut cpu = Cpu::new();
+        cpu.load_bytes(&[0x60, 0x01, 0xF0, 0xFF, 0x60, 0x02]).unwrap();
+        for _ in 0..3 {
+            cpu.emulate_cycle();
+        }
+        assert_eq!(
+            cpu.error(),
+            Some(UnknownOpcode {
+                opcode: 0xF0FF,
+                address: 0x202
+            })
+        );
+        assert_eq!(cpu.cycles(), 1);
+        assert_eq!(cpu.pc(), 0x202);
+        assert_eq!(cpu.v()[0], 1);
+
+        cpu.soft_reset();
+        assert_eq!(cpu.error(), None);
+    }
+
+    // a database that runs this ROM on the VIP at 7 instructions a frame
+    fn db(rom: &[u8]) -> RomDb {
+        RomDb::parse(&format!(
+            "[{}]\ntitle = \"Test\"\nplatform = \"vip\"\nspeed = 7\nquirks = {{ clip = false }}",
+            config::rom_hash(rom)
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn database_settings() {
+        let rom = [0x12, 0x00];
+        let mut cpu = Cpu::new();
+        cpu.load_bytes_with(&rom, &db(&rom)).unwrap();
+        assert_eq!(cpu.rom_info.as_ref().map(|i| i.title.as_str()), Some("Test"));
+        let mut vip = Platform::Vip.quirks();
+        vip.clip = false;
+        assert!(*cpu.quirks() == vip);
+        assert_eq!(cpu.speed(), 7);
+
+        // unknown ROMs keep the defaults
+        let mut cpu = Cpu::new();
+        cpu.load_bytes_with(&[0x13, 0x00], &db(&rom)).unwrap();
+        assert!(cpu.rom_info.is_none());
+        assert!(*cpu.quirks() == Quirks::default());
+        assert_eq!(cpu.speed(), SPEED);
+    }
+
+    #[test]
+    fn database_leaves_what_was_set() {
+        let rom = [0x12, 0x00];
+        let mut cpu = Cpu::new();
+        cpu.set_quirks(Platform::Schip.quirks());
+        cpu.set_speed(20);
+        cpu.load_bytes_with(&rom, &db(&rom)).unwrap();
+        assert!(cpu.rom_info.is_some());
+        assert!(*cpu.quirks() == Platform::Schip.quirks());
+        assert_eq!(cpu.speed(), 20);
+    }
+}
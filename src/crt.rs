@@ -0,0 +1,188 @@
+// Software CRT look, applied to the framebuffer after it has been scaled
+// up. Everything is plain arithmetic on RGBA buffers, so the same input
+// always gives the same image, with or without a window.
+
+// The largest scale the effects are drawn at, however big the window: the
+// renderer scales the image the rest of the way.
+pub static MAX_SCALE: usize = 10;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CrtSettings {
+    pub enabled: bool,
+    // each effect's strength, 0 turns it off
+    pub scanlines: f32,
+    pub grid: f32,
+    pub bloom: f32,
+    pub curvature: f32,
+}
+
+impl Default for CrtSettings {
+    fn default() -> CrtSettings {
+        CrtSettings {
+            enabled: false,
+            scanlines: 0.4,
+            grid: 0.2,
+            bloom: 0.3,
+            curvature: 0.08,
+        }
+    }
+}
+
+impl CrtSettings {
+    // `on`, `off`, or comma separated `<effect>=<strength>` pairs such as
+    // `scanlines=0.5,curvature=0`, which also turn the effects on.
+    pub fn parse(s: &str) -> Result<CrtSettings, String> {
+        let mut settings = CrtSettings::default();
+        match s {
+            "on" => settings.enabled = true,
+            "off" => {}
+            _ => {
+                settings.enabled = true;
+                for pair in s.split(',') {
+                    let mut parts = pair.splitn(2, '=');
+                    let name = parts.next().unwrap_or("").trim();
+                    let value: f32 = match parts.next().map(|v| v.trim().parse()) {
+                        Some(Ok(v)) if v >= 0.0 => v,
+                        _ => return Err(format!("bad value in `{}`", pair)),
+                    };
+                    match name {
+                        "scanlines" => settings.scanlines = value.min(1.0),
+                        "grid" => settings.grid = value.min(1.0),
+                        "bloom" => settings.bloom = value,
+                        "curvature" => settings.curvature = value,
+                        _ => return Err(format!("unknown effect `{}`", name)),
+                    }
+                }
+            }
+        }
+        Ok(settings)
+    }
+
+    // Scales `rgba` (width x height) up by `scale` and applies the effects.
+    pub fn process(&self, rgba: &[u8], width: usize, height: usize, scale: usize) -> Vec<u8> {
+        let (w, h) = (width * scale, height * scale);
+        let mut out = Vec::with_capacity(w * h * 4);
+        for y in 0..h {
+            for x in 0..w {
+                let i = ((y / scale) * width + x / scale) * 4;
+                let mut factor = 1.0;
+                // at scale 1 every pixel would be on the grid
+                if scale > 1 && (x % scale == 0 || y % scale == 0) {
+                    factor -= self.grid;
+                }
+                if y % 2 == 1 {
+                    factor -= self.scanlines;
+                }
+                let factor = factor.max(0.0);
+                for c in 0..3 {
+                    out.push((rgba[i + c] as f32 * factor) as u8);
+                }
+                out.push(255);
+            }
+        }
+
+        if self.bloom > 0.0 {
+            bloom(&mut out, w, h, scale, self.bloom);
+        }
+        if self.curvature > 0.0 {
+            out = curve(&out, w, h, self.curvature);
+        }
+
+        out
+    }
+}
+
+// Adds a blurred copy of the image on top of itself.
+fn bloom(rgba: &mut [u8], w: usize, h: usize, radius: usize, strength: f32) {
+    let mut blurred = rgba.to_vec();
+    box_blur(&mut blurred, w, h, radius, 4, w * 4);
+    box_blur(&mut blurred, h, w, radius, w * 4, 4);
+
+    for (p, b) in rgba.iter_mut().zip(blurred.iter()) {
+        *p = (*p as f32 + *b as f32 * strength).min(255.0) as u8;
+    }
+}
+
+// One-dimensional box blur of `lines` lines of `len` pixels, `step` bytes
+// apart within a line and `stride` bytes between lines.
+fn box_blur(rgba: &mut [u8], len: usize, lines: usize, radius: usize, step: usize, stride: usize) {
+    // running sums make every pixel cost the same whatever the radius
+    let mut sums = vec![0u32; (len + 1) * 3];
+    for l in 0..lines {
+        for i in 0..len {
+            for c in 0..3 {
+                sums[(i + 1) * 3 + c] = sums[i * 3 + c] + rgba[l * stride + i * step + c] as u32;
+            }
+        }
+        for i in 0..len {
+            let (from, to) = (i.saturating_sub(radius), (i + radius).min(len - 1));
+            for c in 0..3 {
+                let sum = sums[(to + 1) * 3 + c] - sums[from * 3 + c];
+                rgba[l * stride + i * step + c] = (sum / (to - from + 1) as u32) as u8;
+            }
+        }
+    }
+}
+
+// Barrel distortion, with black outside the curved screen.
+fn curve(rgba: &[u8], w: usize, h: usize, amount: f32) -> Vec<u8> {
+    let mut out = vec![0u8; rgba.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let u = (x as f32 + 0.5) / w as f32 * 2.0 - 1.0;
+            let v = (y as f32 + 0.5) / h as f32 * 2.0 - 1.0;
+            let su = u * (1.0 + amount * v * v);
+            let sv = v * (1.0 + amount * u * u);
+
+            let o = (y * w + x) * 4;
+            out[o + 3] = 255;
+            if su.abs() > 1.0 || sv.abs() > 1.0 {
+                continue;
+            }
+            let sx = (((su + 1.0) / 2.0 * w as f32) as usize).min(w - 1);
+            let sy = (((sv + 1.0) / 2.0 * h as f32) as usize).min(h - 1);
+            let i = (sy * w + sx) * 4;
+            out[o..o + 3].copy_from_slice(&rgba[i..i + 3]);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 4x2 image of grey levels
+    fn image() -> Vec<u8> {
+        [0u8, 60, 120, 180, 240, 200, 100, 30]
+            .iter()
+            .flat_map(|&v| vec![v, v, v, 255])
+            .collect()
+    }
+
+    #[test]
+    fn same_input_same_output() {
+        let settings =
+            CrtSettings::parse("scanlines=0.5,grid=0.3,bloom=0.4,curvature=0.1").unwrap();
+        let first = settings.process(&image(), 4, 2, 3);
+        assert_eq!(first.len(), 12 * 6 * 4);
+        for _ in 0..3 {
+            assert_eq!(settings.process(&image(), 4, 2, 3), first);
+        }
+    }
+
+    #[test]
+    fn grid_lines_between_pixels() {
+        let settings = CrtSettings::parse("scanlines=0,grid=0.5,bloom=0,curvature=0").unwrap();
+        let out = settings.process(&image(), 4, 2, 2);
+        // the first row is on the grid, the second only in its first column
+        let row: Vec<u8> = out[8 * 4..16 * 4].chunks(4).map(|p| p[0]).collect();
+        assert_eq!(row, [0, 0, 30, 60, 60, 120, 90, 180]);
+    }
+
+    #[test]
+    fn no_grid_at_scale_one() {
+        let settings = CrtSettings::parse("scanlines=0,grid=0.5,bloom=0,curvature=0").unwrap();
+        assert_eq!(settings.process(&image(), 4, 2, 1), image());
+    }
+}
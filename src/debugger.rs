@@ -0,0 +1,399 @@
+use cpu::Cpu;
+use disasm::disassemble;
+use std::collections::{BTreeSet, VecDeque};
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use symbols::Symbols;
+use trace;
+
+// Periodic checkpoints of the whole Cpu plus every change of the keypad.
+// Since a Cpu carries its own RNG and timers, replaying the recorded keys
+// from a checkpoint reaches exactly the same state again.
+pub struct History {
+    interval: u64,
+    limit: usize,
+    checkpoints: VecDeque<Cpu>,
+    inputs: VecDeque<(u64, [u8; 16])>,
+}
+
+impl History {
+    pub fn new(interval: u64, limit: usize) -> History {
+        History {
+            interval,
+            limit,
+            checkpoints: VecDeque::new(),
+            inputs: VecDeque::new(),
+        }
+    }
+
+    // Call before every cycle that is run live.
+    pub fn record(&mut self, cpu: &Cpu) {
+        let last = self.checkpoints.back().map(|c| c.cycles());
+        if last.is_none()
+            || (cpu.cycles().is_multiple_of(self.interval) && last != Some(cpu.cycles()))
+        {
+            self.checkpoints.push_back(cpu.clone());
+        }
+
+        if self.checkpoints.len() > self.limit {
+            self.checkpoints.pop_front();
+            let oldest = self.checkpoints[0].cycles();
+            while self.inputs.len() > 1 && self.inputs[1].0 <= oldest {
+                self.inputs.pop_front();
+            }
+        }
+
+        if self.inputs.back().is_none_or(|&(_, k)| k != cpu.key) {
+            self.inputs.push_back((cpu.cycles(), cpu.key));
+        }
+    }
+
+    // Forgets everything recorded.
+    pub fn clear(&mut self) {
+        self.checkpoints.clear();
+        self.inputs.clear();
+    }
+
+    pub fn oldest(&self) -> Option<u64> {
+        self.checkpoints.front().map(|c| c.cycles())
+    }
+
+    // Puts the Cpu back to the state it had at the start of `cycle`. The
+    // recorded future is dropped, as running on from here may diverge.
+    pub fn rewind_to(&mut self, cpu: &mut Cpu, cycle: u64) -> bool {
+        let checkpoint = match self.checkpoints.iter().rev().find(|c| c.cycles() <= cycle) {
+            Some(checkpoint) => checkpoint,
+            None => return false,
+        };
+
+        let mut past = self.replay(checkpoint, cycle, |_| {});
+        // coverage describes everything the program ever did, keep it
+        past.coverage = mem::replace(&mut cpu.coverage, past.coverage.clone());
+        past.gpu.draw_flag = true;
+        *cpu = past;
+
+        while self.checkpoints.back().is_some_and(|c| c.cycles() > cycle) {
+            self.checkpoints.pop_back();
+        }
+        while self.inputs.back().is_some_and(|&(c, _)| c > cycle) {
+            self.inputs.pop_back();
+        }
+
+        true
+    }
+
+    // The last cycle before the current one whose starting state matches.
+    pub fn find_back<F: Fn(&Cpu) -> bool>(&self, cpu: &Cpu, pred: F) -> Option<u64> {
+        let mut end = cpu.cycles();
+
+        for checkpoint in self.checkpoints.iter().rev() {
+            if checkpoint.cycles() >= end {
+                continue;
+            }
+
+            let mut found = None;
+            self.replay(checkpoint, end, |c| {
+                if pred(c) {
+                    found = Some(c.cycles());
+                }
+            });
+            if found.is_some() {
+                return found;
+            }
+
+            end = checkpoint.cycles();
+        }
+
+        None
+    }
+
+    fn replay<F: FnMut(&Cpu)>(&self, from: &Cpu, to: u64, mut visit: F) -> Cpu {
+        let mut cpu = from.clone();
+        while cpu.cycles() < to {
+            cpu.key = self.keys_at(cpu.cycles());
+            visit(&cpu);
+            cpu.emulate_cycle();
+        }
+        cpu.key = self.keys_at(to);
+        cpu
+    }
+
+    fn keys_at(&self, cycle: u64) -> [u8; 16] {
+        self.inputs
+            .iter()
+            .rev()
+            .find(|&&(c, _)| c <= cycle)
+            .map_or([0; 16], |&(_, k)| k)
+    }
+}
+
+static HELP: &str = "\
+step (s) [n]            run n instructions
+continue (c)            run until the next breakpoint
+reverse-step (rs) [n]   go back n instructions
+reverse-continue (rc)   go back to the previous breakpoint
+break (b) <addr>        set a breakpoint
+delete (d) <addr>       remove a breakpoint
+regs (r)                show the registers
+quit (q)                exit the emulator
+";
+
+// A small command line debugger on stdin. While paused the emulator only
+// advances through debugger commands.
+pub struct Debugger {
+    breakpoints: BTreeSet<usize>,
+    history: History,
+    symbols: Option<Symbols>,
+    paused: bool,
+    // skip the breakpoint under pc once after continuing from it
+    resuming: bool,
+    // the prompt and what the commands print, stdout unless set_output
+    // says otherwise
+    out: Box<dyn Write>,
+    // where every instruction run goes, with set_trace
+    trace: Option<Box<dyn Write>>,
+}
+
+impl Debugger {
+    pub fn new(symbols: Option<Symbols>) -> Debugger {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            // a checkpoint every 300 cycles, the last 600 of them: a second
+            // apart and ten minutes back at the default 5 instructions a frame
+            history: History::new(300, 600),
+            symbols,
+            paused: false,
+            resuming: false,
+            out: Box::new(io::stdout()),
+            trace: None,
+        }
+    }
+
+    // Sends the prompt and output somewhere else, like stderr when stdout
+    // carries raw video.
+    pub fn set_output(&mut self, out: Box<dyn Write>) {
+        self.out = out;
+    }
+
+    // Writes every instruction to `trace` before it runs.
+    pub fn set_trace(&mut self, trace: Box<dyn Write>) {
+        self.trace = Some(trace);
+    }
+
+    // Starts the history over from the Cpu as it is now. Call it after
+    // changing the Cpu between cycles, like a reset or a new speed, which
+    // replaying from the old checkpoints would not do.
+    pub fn reset_history(&mut self, cpu: &Cpu) {
+        self.history.clear();
+        self.history.record(cpu);
+    }
+
+    pub fn symbols(&self) -> Option<&Symbols> {
+        self.symbols.as_ref()
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        if !self.paused {
+            writeln!(self.out, "Paused, type `help` for the debugger commands").ok();
+            self.paused = true;
+        }
+    }
+
+    // Runs one cycle, or one debugger command while paused. Returns false
+    // when the user quits or the program stopped on an unknown opcode.
+    pub fn run_cycle(&mut self, cpu: &mut Cpu) -> bool {
+        if cpu.error().is_some() {
+            return false;
+        }
+        if !self.paused && !self.resuming && self.breakpoints.contains(&cpu.pc()) {
+            writeln!(self.out, "Breakpoint").ok();
+            self.paused = true;
+        }
+        self.resuming = false;
+
+        if self.paused {
+            return self.command(cpu);
+        }
+
+        self.step(cpu);
+        true
+    }
+
+    fn step(&mut self, cpu: &mut Cpu) {
+        self.history.record(cpu);
+        if let Some(mut out) = self.trace.take() {
+            match trace::write(&mut out, cpu, self.symbols.as_ref()) {
+                Ok(()) => self.trace = Some(out),
+                Err(e) => eprintln!("Error Writing Trace {}", e),
+            }
+        }
+        cpu.emulate_cycle();
+    }
+
+    fn command(&mut self, cpu: &mut Cpu) -> bool {
+        self.show(cpu);
+        write!(self.out, "(chip8) ").ok();
+        self.out.flush().ok();
+
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+            return false;
+        }
+
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let arg = words.next();
+        let count = arg.and_then(|n| n.parse().ok()).unwrap_or(1);
+
+        match command {
+            "s" | "step" => {
+                for _ in 0..count {
+                    self.step(cpu);
+                }
+            }
+            "c" | "continue" => {
+                self.paused = false;
+                self.resuming = true;
+            }
+            "rs" | "reverse-step" => {
+                let target = cpu.cycles().saturating_sub(count);
+                if !self.history.rewind_to(cpu, target) {
+                    writeln!(self.out, "No history before cycle {}", cpu.cycles()).ok();
+                }
+            }
+            "rc" | "reverse-continue" => {
+                let found = {
+                    let breakpoints = &self.breakpoints;
+                    self.history
+                        .find_back(cpu, |c| breakpoints.contains(&c.pc()))
+                };
+                match found.or_else(|| self.history.oldest()) {
+                    Some(target) => {
+                        if found.is_none() {
+                            writeln!(
+                                self.out,
+                                "No earlier breakpoint, back to the oldest checkpoint"
+                            )
+                            .ok();
+                        }
+                        self.history.rewind_to(cpu, target);
+                    }
+                    None => {
+                        writeln!(self.out, "No history before cycle {}", cpu.cycles()).ok();
+                    }
+                }
+            }
+            "b" | "break" => match arg.and_then(|a| self.parse_addr(a)) {
+                Some(addr) => {
+                    self.breakpoints.insert(addr);
+                }
+                None => {
+                    writeln!(self.out, "Usage: break <address|label>").ok();
+                }
+            },
+            "d" | "delete" => match arg.and_then(|a| self.parse_addr(a)) {
+                Some(addr) => {
+                    self.breakpoints.remove(&addr);
+                }
+                None => {
+                    writeln!(self.out, "Usage: delete <address|label>").ok();
+                }
+            },
+            "r" | "regs" => {
+                writeln!(self.out, "{}", cpu.registers()).ok();
+            }
+            "q" | "quit" => return false,
+            "" => {}
+            _ => {
+                write!(self.out, "{}", HELP).ok();
+            }
+        }
+
+        true
+    }
+
+    fn show(&mut self, cpu: &Cpu) {
+        let pc = cpu.pc();
+        let memory = cpu.memory();
+        let opcode = (memory[pc] as u16) << 8 | memory[(pc + 1) % memory.len()] as u16;
+
+        if let Some(label) = self.symbols.as_ref().and_then(|s| s.get(pc)) {
+            writeln!(self.out, "{}:", label.name).ok();
+        }
+        writeln!(
+            self.out,
+            "{:03X}  {:04X}  {}",
+            pc,
+            opcode,
+            disassemble(opcode, cpu.quirks(), self.symbols.as_ref())
+        )
+        .ok();
+    }
+
+    fn parse_addr(&self, s: &str) -> Option<usize> {
+        if let Some(addr) = self.symbols.as_ref().and_then(|sym| sym.lookup(s)) {
+            return Some(addr);
+        }
+        usize::from_str_radix(s.trim_start_matches("0x"), 16).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // V0 counts up forever
+    fn counter() -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.load_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        cpu
+    }
+
+    fn run(cpu: &mut Cpu, history: &mut History, cycles: u64) {
+        for _ in 0..cycles {
+            history.record(cpu);
+            cpu.emulate_cycle();
+        }
+    }
+
+    #[test]
+    fn rewind_replays_to_the_same_state() {
+        let mut cpu = counter();
+        let mut history = History::new(4, 10);
+        run(&mut cpu, &mut history, 10);
+        let at_five = {
+            let mut cpu = counter();
+            run(&mut cpu, &mut History::new(4, 10), 5);
+            cpu
+        };
+
+        assert!(history.rewind_to(&mut cpu, 5));
+        assert_eq!(cpu.cycles(), 5);
+        assert_eq!(cpu.memory(), at_five.memory());
+        assert_eq!(cpu.v(), at_five.v());
+        assert_eq!(cpu.pc(), at_five.pc());
+    }
+
+    #[test]
+    fn cleared_history_stops_at_the_reset() {
+        let mut cpu = counter();
+        let mut history = History::new(4, 10);
+        run(&mut cpu, &mut history, 10);
+
+        cpu.soft_reset();
+        history.clear();
+        history.record(&cpu);
+        assert_eq!(history.oldest(), Some(10));
+        run(&mut cpu, &mut history, 3);
+
+        assert!(!history.rewind_to(&mut cpu, 9));
+        assert!(history.rewind_to(&mut cpu, 10));
+        assert_eq!(cpu.pc(), 0x200);
+        assert_eq!(cpu.v()[0], 0);
+    }
+}
@@ -0,0 +1,109 @@
+use quirks::Quirks;
+use symbols::Symbols;
+
+// Turns a single opcode into its mnemonic, using the same names as the
+// comments in cpu.rs. With symbols, addresses are shown as labels. The
+// quirks decide which register BNNN adds, as they do when it runs.
+pub fn disassemble(opcode: u16, quirks: &Quirks, symbols: Option<&Symbols>) -> String {
+    let addr = |nnn: u16| match symbols {
+        Some(symbols) => symbols.label(nnn as usize),
+        None => format!("{:#05X}", nnn),
+    };
+    let x = (opcode & 0x0F00) >> 8;
+    let y = (opcode & 0x00F0) >> 4;
+    let n = opcode & 0x000F;
+    let kk = opcode & 0x00FF;
+    let nnn = opcode & 0x0FFF;
+
+    match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => "CLS".to_string(),
+            0x00EE => "RET".to_string(),
+            _ => format!("SYS {:#05X}", nnn),
+        },
+        0x1000 => format!("JP {}", addr(nnn)),
+        0x2000 => format!("CALL {}", addr(nnn)),
+        0x3000 => format!("SE V{:X}, {:#04X}", x, kk),
+        0x4000 => format!("SNE V{:X}, {:#04X}", x, kk),
+        0x5000 if n == 0 => format!("SE V{:X}, V{:X}", x, y),
+        0x6000 => format!("LD V{:X}, {:#04X}", x, kk),
+        0x7000 => format!("ADD V{:X}, {:#04X}", x, kk),
+        0x8000 => match n {
+            0x0 => format!("LD V{:X}, V{:X}", x, y),
+            0x1 => format!("OR V{:X}, V{:X}", x, y),
+            0x2 => format!("AND V{:X}, V{:X}", x, y),
+            0x3 => format!("XOR V{:X}, V{:X}", x, y),
+            0x4 => format!("ADD V{:X}, V{:X}", x, y),
+            0x5 => format!("SUB V{:X}, V{:X}", x, y),
+            0x6 => format!("SHR V{:X}", x),
+            0x7 => format!("SUBN V{:X}, V{:X}", x, y),
+            0xE => format!("SHL V{:X}", x),
+            _ => unknown(opcode),
+        },
+        0x9000 if n == 0 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA000 => format!("LD I, {}", addr(nnn)),
+        0xB000 if quirks.jump => format!("JP V{:X}, {}", x, addr(nnn)),
+        0xB000 => format!("JP V0, {}", addr(nnn)),
+        0xC000 => format!("RND V{:X}, {:#04X}", x, kk),
+        0xD000 => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        0xE000 => match kk {
+            0x9E => format!("SKP V{:X}", x),
+            0xA1 => format!("SKNP V{:X}", x),
+            _ => unknown(opcode),
+        },
+        0xF000 => match kk {
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            _ => unknown(opcode),
+        },
+        _ => unknown(opcode),
+    }
+}
+
+// The address an instruction jumps to, calls or points I at.
+pub fn target(opcode: u16) -> Option<usize> {
+    match opcode & 0xF000 {
+        0x1000 | 0x2000 | 0xA000 | 0xB000 => Some((opcode & 0x0FFF) as usize),
+        _ => None,
+    }
+}
+
+fn unknown(opcode: u16) -> String {
+    format!("DW {:#06X}", opcode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quirks::Platform;
+
+    #[test]
+    fn labels_for_addresses() {
+        let symbols = Symbols::parse("2A4 draw_paddle\n").unwrap();
+        let quirks = Platform::Modern.quirks();
+        assert_eq!(disassemble(0x22A4, &quirks, None), "CALL 0x2A4");
+        assert_eq!(
+            disassemble(0x22A4, &quirks, Some(&symbols)),
+            "CALL draw_paddle"
+        );
+        assert_eq!(disassemble(0xA300, &quirks, Some(&symbols)), "LD I, L300");
+        // only addresses are labelled
+        assert_eq!(disassemble(0x62A4, &quirks, Some(&symbols)), "LD V2, 0xA4");
+    }
+
+    #[test]
+    fn jump_follows_the_quirk() {
+        let mut quirks = Platform::Modern.quirks();
+        quirks.jump = false;
+        assert_eq!(disassemble(0xB234, &quirks, None), "JP V0, 0x234");
+        quirks.jump = true;
+        assert_eq!(disassemble(0xB234, &quirks, None), "JP V2, 0x234");
+    }
+}
@@ -0,0 +1,291 @@
+use crt::{self, CrtSettings};
+use filter::{Filter, FilterMode};
+use gpu::{Gpu, HEIGHT, WIDTH};
+use launcher::{self, Launcher};
+use osd::{self, Osd};
+use palette::Palette;
+use sdl2;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Texture};
+use sdl2::video::FullscreenType;
+
+// Default window scale, also used for screenshots and recordings.
+pub static SCALE: u32 = 10;
+
+// RGBA byte order in memory, whatever the host's endianness
+#[cfg(target_endian = "little")]
+const RGBA: PixelFormatEnum = PixelFormatEnum::ABGR8888;
+#[cfg(target_endian = "big")]
+const RGBA: PixelFormatEnum = PixelFormatEnum::RGBA8888;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ScaleMode {
+    // as large as fits while keeping the aspect ratio, with black bars
+    Fit,
+    // like Fit, but only whole multiples of the native size
+    Integer,
+    // fill the whole window
+    Stretch,
+}
+
+impl ScaleMode {
+    pub fn parse(s: &str) -> Option<ScaleMode> {
+        match s {
+            "fit" => Some(ScaleMode::Fit),
+            "integer" => Some(ScaleMode::Integer),
+            "stretch" => Some(ScaleMode::Stretch),
+            _ => None,
+        }
+    }
+
+    pub fn next(self) -> ScaleMode {
+        match self {
+            ScaleMode::Fit => ScaleMode::Integer,
+            ScaleMode::Integer => ScaleMode::Stretch,
+            ScaleMode::Stretch => ScaleMode::Fit,
+        }
+    }
+
+    // Where the framebuffer goes in an output of the given size.
+    pub fn viewport(self, width: u32, height: u32) -> Rect {
+        let (w, h) = (WIDTH as u32, HEIGHT as u32);
+        let (vw, vh) = match self {
+            ScaleMode::Stretch => (width, height),
+            ScaleMode::Fit => {
+                if width * h > height * w {
+                    (height * w / h, height)
+                } else {
+                    (width, width * h / w)
+                }
+            }
+            ScaleMode::Integer => {
+                let scale = (width / w).min(height / h).max(1);
+                (w * scale, h * scale)
+            }
+        };
+
+        Rect::new(
+            (width as i32 - vw as i32) / 2,
+            (height as i32 - vh as i32) / 2,
+            vw.max(1),
+            vh.max(1),
+        )
+    }
+}
+
+pub struct Display {
+    canvas: sdl2::render::Canvas<sdl2::video::Window>,
+    // the framebuffer is uploaded here and scaled by the renderer
+    texture: Texture,
+    rgba: Vec<u8>,
+    // or, with the CRT effects, here after scaling by `crt_scale`
+    crt_texture: Texture,
+    crt_scale: usize,
+    // the launcher is drawn at its own size
+    launcher_texture: Texture,
+    // the OSD goes over the screen, blended by its alpha
+    osd_texture: Texture,
+    // so that the screen is drawn once more without it
+    osd_shown: bool,
+    scale: u32,
+    crt: CrtSettings,
+    palette: Palette,
+    scale_mode: ScaleMode,
+    filter: Filter,
+    // the last frame the filter was advanced to
+    frame: Option<u64>,
+}
+
+impl Display {
+    // The window starts at `scale` times the native size. benches/render.rs
+    // builds its fill_rect window the same way, keep them in step.
+    pub fn new(_sdlcontext: &sdl2::Sdl, title: &str, scale: u32) -> Display {
+        let canvas = _sdlcontext
+            .video()
+            .unwrap()
+            .window(title, WIDTH as u32 * scale, HEIGHT as u32 * scale)
+            .position_centered()
+            .resizable()
+            .opengl()
+            .build()
+            .unwrap()
+            .into_canvas()
+            .build()
+            .unwrap();
+
+        // with sdl2's unsafe_textures the textures come from the canvas and
+        // are freed along with its renderer
+        let texture = canvas
+            .create_texture_streaming(RGBA, WIDTH as u32, HEIGHT as u32)
+            .unwrap();
+        let crt_scale = (scale as usize).min(crt::MAX_SCALE);
+        let crt_texture = canvas
+            .create_texture_streaming(
+                RGBA,
+                (WIDTH * crt_scale) as u32,
+                (HEIGHT * crt_scale) as u32,
+            )
+            .unwrap();
+        let launcher_texture = canvas
+            .create_texture_streaming(
+                RGBA,
+                launcher::SCREEN_WIDTH as u32,
+                launcher::SCREEN_HEIGHT as u32,
+            )
+            .unwrap();
+        let mut osd_texture = canvas
+            .create_texture_streaming(RGBA, osd::WIDTH as u32, osd::HEIGHT as u32)
+            .unwrap();
+        osd_texture.set_blend_mode(BlendMode::Blend);
+
+        Display {
+            canvas,
+            texture,
+            rgba: Vec::with_capacity(WIDTH * HEIGHT * 4),
+            crt_texture,
+            crt_scale,
+            launcher_texture,
+            osd_texture,
+            osd_shown: false,
+            scale,
+            crt: CrtSettings::default(),
+            palette: Palette::default(),
+            scale_mode: ScaleMode::Fit,
+            filter: Filter::default(),
+            frame: None,
+        }
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    pub fn set_palette(&mut self, gpu: &mut Gpu, palette: Palette) {
+        self.palette = palette;
+        gpu.draw_flag = true;
+    }
+
+    pub fn scale_mode(&self) -> ScaleMode {
+        self.scale_mode
+    }
+
+    pub fn set_scale_mode(&mut self, gpu: &mut Gpu, scale_mode: ScaleMode) {
+        self.scale_mode = scale_mode;
+        gpu.draw_flag = true;
+    }
+
+    pub fn filter_mode(&self) -> FilterMode {
+        self.filter.mode()
+    }
+
+    pub fn set_filter_mode(&mut self, gpu: &mut Gpu, mode: FilterMode) -> Result<(), String> {
+        self.filter.set_mode(mode)?;
+        gpu.draw_flag = true;
+        Ok(())
+    }
+
+    pub fn crt(&self) -> CrtSettings {
+        self.crt
+    }
+
+    pub fn set_crt(&mut self, gpu: &mut Gpu, crt: CrtSettings) {
+        self.crt = crt;
+        gpu.draw_flag = true;
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        if let Err(e) = self.canvas.window_mut().set_title(title) {
+            eprintln!("Error Setting Title {}", e);
+        }
+    }
+
+    pub fn toggle_fullscreen(&mut self, gpu: &mut Gpu) {
+        let window = self.canvas.window_mut();
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        if let Err(e) = window.set_fullscreen(fullscreen) {
+            eprintln!("Error Switching Fullscreen {}", e);
+        }
+        gpu.draw_flag = true;
+    }
+
+    // `frame` counts emulated frames; filters move on once per frame and
+    // then need a redraw even if the framebuffer is unchanged. The OSD is
+    // drawn over the screen, scaled the same way.
+    pub fn render_screen(&mut self, gpu: &mut Gpu, frame: u64, osd: &mut Osd) {
+        let advance = self.frame != Some(frame);
+        let filtering = self.filter.mode() != FilterMode::Off;
+        let redraw = gpu.draw_flag || (filtering && advance) || osd.visible() || self.osd_shown;
+        if !redraw {
+            return;
+        }
+        self.frame = Some(frame);
+
+        let (width, height) = self
+            .canvas
+            .output_size()
+            .unwrap_or((WIDTH as u32 * self.scale, HEIGHT as u32 * self.scale));
+        let view = self.scale_mode.viewport(width, height);
+
+        self.filter
+            .apply(gpu.pixels(), &self.palette, advance, &mut self.rgba);
+
+        //first clear screen, black around the framebuffer
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+
+        if self.crt.enabled {
+            let scale = self.crt_scale;
+            let image = self.crt.process(&self.rgba, WIDTH, HEIGHT, scale);
+            self.crt_texture
+                .update(None, &image, WIDTH * scale * 4)
+                .unwrap();
+            self.canvas.copy(&self.crt_texture, None, Some(view)).ok();
+        } else {
+            self.texture.update(None, &self.rgba, WIDTH * 4).unwrap();
+            self.canvas.copy(&self.texture, None, Some(view)).ok();
+        }
+
+        // the framebuffer is uploaded, so rgba is free for the OSD
+        self.osd_shown = osd.render(&mut self.rgba);
+        if self.osd_shown {
+            self.osd_texture
+                .update(None, &self.rgba, osd::WIDTH * 4)
+                .unwrap();
+            self.canvas.copy(&self.osd_texture, None, Some(view)).ok();
+        }
+        self.canvas.present();
+
+        gpu.draw_flag = false;
+    }
+
+    // Draws the launcher in place of the framebuffer, in the palette's
+    // colours and scaled the same way, with the OSD over it.
+    pub fn render_launcher(&mut self, launcher: &Launcher, osd: &mut Osd) {
+        let (width, height) = self
+            .canvas
+            .output_size()
+            .unwrap_or((WIDTH as u32 * self.scale, HEIGHT as u32 * self.scale));
+        let view = self.scale_mode.viewport(width, height);
+
+        launcher.render(&self.palette, &mut self.rgba);
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        self.launcher_texture
+            .update(None, &self.rgba, launcher::SCREEN_WIDTH * 4)
+            .unwrap();
+        self.canvas
+            .copy(&self.launcher_texture, None, Some(view))
+            .ok();
+        if osd.render(&mut self.rgba) {
+            self.osd_texture
+                .update(None, &self.rgba, osd::WIDTH * 4)
+                .unwrap();
+            self.canvas.copy(&self.osd_texture, None, Some(view)).ok();
+        }
+        self.canvas.present();
+    }
+}
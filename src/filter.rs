@@ -0,0 +1,218 @@
+use palette::Palette;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FilterMode {
+    Off,
+    // lit pixels fade out over this many frames after they go dark
+    Phosphor(u32),
+    // every frame is mixed half and half with the one before
+    Blend,
+}
+
+impl FilterMode {
+    // `off`, `blend`, `phosphor` or `phosphor:<frames>`
+    pub fn parse(s: &str) -> Option<FilterMode> {
+        let mut parts = s.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("off"), None) => Some(FilterMode::Off),
+            (Some("blend"), None) => Some(FilterMode::Blend),
+            (Some("phosphor"), None) => Some(FilterMode::Phosphor(4)),
+            (Some("phosphor"), Some(n)) => match n.parse() {
+                Ok(n) if n > 0 => Some(FilterMode::Phosphor(n)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn next(self) -> FilterMode {
+        match self {
+            FilterMode::Off => FilterMode::Phosphor(4),
+            FilterMode::Phosphor(_) => FilterMode::Blend,
+            FilterMode::Blend => FilterMode::Off,
+        }
+    }
+}
+
+// Anti-flicker filter over the framebuffer. It only depends on the frames
+// it is given, so the same frames always give the same output.
+pub struct Filter {
+    mode: FilterMode,
+    // phosphor: brightness and colour of every pixel when it was last lit
+    levels: Vec<u8>,
+    lit: Vec<u8>,
+    // blend: the previous frame, unfiltered
+    previous: Vec<u8>,
+}
+
+impl Filter {
+    // Phosphor needs at least one frame to fade over.
+    pub fn new(mode: FilterMode) -> Result<Filter, String> {
+        if mode == FilterMode::Phosphor(0) {
+            return Err("phosphor needs at least 1 frame to fade over".to_string());
+        }
+        Ok(Filter {
+            mode,
+            levels: Vec::new(),
+            lit: Vec::new(),
+            previous: Vec::new(),
+        })
+    }
+
+    pub fn mode(&self) -> FilterMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: FilterMode) -> Result<(), String> {
+        *self = Filter::new(mode)?;
+        Ok(())
+    }
+
+    // Converts the framebuffer to RGBA. `advance` moves on by one frame;
+    // without it the output is refreshed for changed pixels only, e.g.
+    // while the emulation is paused.
+    pub fn apply(&mut self, pixels: &[u8], palette: &Palette, advance: bool, rgba: &mut Vec<u8>) {
+        match self.mode {
+            FilterMode::Off => palette.to_rgba(pixels, rgba),
+            FilterMode::Phosphor(frames) => self.phosphor(pixels, palette, frames, advance, rgba),
+            FilterMode::Blend => self.blend(pixels, palette, advance, rgba),
+        }
+    }
+
+    fn phosphor(
+        &mut self,
+        pixels: &[u8],
+        palette: &Palette,
+        frames: u32,
+        advance: bool,
+        rgba: &mut Vec<u8>,
+    ) {
+        if self.levels.len() != pixels.len() {
+            self.levels = vec![0; pixels.len()];
+            self.lit = vec![0; pixels.len()];
+        }
+        let decay = 255u32.div_ceil(frames);
+        let background = palette.background();
+
+        rgba.clear();
+        for (i, &p) in pixels.iter().enumerate() {
+            if p != 0 {
+                self.levels[i] = 255;
+                self.lit[i] = p;
+            } else if advance {
+                self.levels[i] = self.levels[i].saturating_sub(decay as u8);
+            }
+
+            let colour = palette.colour(self.lit[i]);
+            for c in 0..3 {
+                let (from, to) = (background[c] as u32, colour[c] as u32);
+                let level = self.levels[i] as u32;
+                rgba.push(((from * (255 - level) + to * level) / 255) as u8);
+            }
+            rgba.push(255);
+        }
+    }
+
+    fn blend(&mut self, pixels: &[u8], palette: &Palette, advance: bool, rgba: &mut Vec<u8>) {
+        let mut current = Vec::with_capacity(pixels.len() * 4);
+        palette.to_rgba(pixels, &mut current);
+        if self.previous.len() != current.len() {
+            self.previous = current.clone();
+        }
+
+        rgba.clear();
+        for (a, b) in current.iter().zip(self.previous.iter()) {
+            rgba.push(((*a as u16 + *b as u16) / 2) as u8);
+        }
+
+        if advance {
+            self.previous = current;
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Filter {
+        Filter::new(FilterMode::Off).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn black_and_white() -> Palette {
+        Palette::parse("000000,ffffff").unwrap()
+    }
+
+    // the red channel of every pixel
+    fn red(rgba: &[u8]) -> Vec<u8> {
+        rgba.chunks(4).map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn phosphor_fades_over_its_frames() {
+        let palette = black_and_white();
+        let mut filter = Filter::new(FilterMode::Phosphor(4)).unwrap();
+        let mut rgba = Vec::new();
+
+        filter.apply(&[1, 0], &palette, true, &mut rgba);
+        assert_eq!(rgba, [255, 255, 255, 255, 0, 0, 0, 255]);
+
+        let mut levels = Vec::new();
+        for _ in 0..5 {
+            filter.apply(&[0, 0], &palette, true, &mut rgba);
+            levels.push(red(&rgba)[0]);
+        }
+        assert_eq!(levels, [191, 127, 63, 0, 0]);
+    }
+
+    #[test]
+    fn phosphor_holds_without_advance() {
+        let palette = black_and_white();
+        let mut filter = Filter::new(FilterMode::Phosphor(4)).unwrap();
+        let mut rgba = Vec::new();
+
+        filter.apply(&[1], &palette, true, &mut rgba);
+        filter.apply(&[0], &palette, true, &mut rgba);
+        for _ in 0..3 {
+            filter.apply(&[0], &palette, false, &mut rgba);
+            assert_eq!(red(&rgba), [191]);
+        }
+        // a pixel lit again shows at once, paused or not
+        filter.apply(&[1], &palette, false, &mut rgba);
+        assert_eq!(red(&rgba), [255]);
+    }
+
+    #[test]
+    fn blend_averages_with_the_previous_frame() {
+        let palette = black_and_white();
+        let mut filter = Filter::new(FilterMode::Blend).unwrap();
+        let mut rgba = Vec::new();
+
+        // the first frame has nothing to blend with
+        filter.apply(&[1, 0], &palette, true, &mut rgba);
+        assert_eq!(red(&rgba), [255, 0]);
+
+        filter.apply(&[0, 1], &palette, false, &mut rgba);
+        assert_eq!(red(&rgba), [127, 127]);
+        filter.apply(&[0, 1], &palette, true, &mut rgba);
+        assert_eq!(red(&rgba), [127, 127]);
+        assert_eq!(rgba[3], 255);
+
+        // with the previous frame now the same, there is nothing to mix
+        filter.apply(&[0, 1], &palette, true, &mut rgba);
+        assert_eq!(red(&rgba), [0, 255]);
+    }
+
+    #[test]
+    fn phosphor_needs_a_frame() {
+        assert!(Filter::new(FilterMode::Phosphor(0)).is_err());
+        assert!(Filter::default().set_mode(FilterMode::Phosphor(0)).is_err());
+        assert_eq!(FilterMode::parse("phosphor:0"), None);
+        assert_eq!(
+            FilterMode::parse("phosphor:2"),
+            Some(FilterMode::Phosphor(2))
+        );
+    }
+}
@@ -0,0 +1,85 @@
+// A 3x5 font for text drawn over the screen, in capitals only. Each glyph
+// is 15 bits, the rows from the top and the top left pixel highest, for the
+// characters from space to underscore.
+static GLYPHS: [u16; 64] = [
+    0x0000, 0x2482, 0x5A00, 0x5F7D, 0x3C9E, 0x52A5, 0x2AAB, 0x2400, 0x1491, 0x4494, 0x55D5, 0x05D0,
+    0x0014, 0x01C0, 0x0002, 0x12A4, 0x7B6F, 0x2C97, 0x73E7, 0x72CF, 0x5BC9, 0x79CF, 0x79EF, 0x7252,
+    0x7BEF, 0x7BCF, 0x0410, 0x0414, 0x1511, 0x0E38, 0x4454, 0x72C2, 0x7B63, 0x7BED, 0x6BAE, 0x3923,
+    0x6B6E, 0x79A7, 0x79A4, 0x396B, 0x5BED, 0x7497, 0x126A, 0x5BAD, 0x4927, 0x5FED, 0x6B6D, 0x2B6A,
+    0x6BA4, 0x2B73, 0x6BAD, 0x388E, 0x7492, 0x5B6B, 0x5B52, 0x5BFD, 0x5AAD, 0x5A92, 0x72A7, 0x6926,
+    0x4889, 0x324B, 0x2A00, 0x0007,
+];
+
+pub static GLYPH_WIDTH: usize = 3;
+pub static GLYPH_HEIGHT: usize = 5;
+// glyphs are drawn in cells this big, to leave a gap between them
+pub static CELL_WIDTH: usize = 4;
+pub static CELL_HEIGHT: usize = 6;
+
+// The glyph for `c`; lower case letters look like capitals and characters
+// without a glyph like a question mark.
+pub fn glyph(c: char) -> u16 {
+    let c = c.to_ascii_uppercase() as usize;
+    match c {
+        0x20..=0x5F => GLYPHS[c - 0x20],
+        // |
+        0x7C => 0x2492,
+        _ => GLYPHS['?' as usize - 0x20],
+    }
+}
+
+// Draws `text` into an RGBA image `width` pixels wide, from the cell with
+// its top left corner at (x, y). Whatever falls outside is cut off.
+pub fn draw_text(rgba: &mut [u8], width: usize, x: usize, y: usize, text: &str, colour: [u8; 3]) {
+    let height = rgba.len() / 4 / width;
+    for (i, c) in text.chars().enumerate() {
+        let bits = glyph(c);
+        for row in 0..GLYPH_HEIGHT {
+            for col in 0..GLYPH_WIDTH {
+                let bit = 14 - (row * GLYPH_WIDTH + col);
+                let (px, py) = (x + i * CELL_WIDTH + col, y + row);
+                if bits & (1 << bit) == 0 || px >= width || py >= height {
+                    continue;
+                }
+                let p = (py * width + px) * 4;
+                rgba[p..p + 3].copy_from_slice(&colour);
+                rgba[p + 3] = 255;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyphs() {
+        assert_eq!(glyph(' '), 0);
+        // 1 0 1 / 1 0 1 / 1 1 1 / 1 0 1 / 1 0 1
+        assert_eq!(glyph('H'), 0b101_101_111_101_101);
+        assert_eq!(glyph('h'), glyph('H'));
+        assert_eq!(glyph('|'), 0b010_010_010_010_010);
+        assert_eq!(glyph('é'), glyph('?'));
+        assert_eq!(glyph('~'), glyph('?'));
+        // every printable character but space has some pixels
+        assert!(('!'..='_').all(|c| glyph(c) != 0 && glyph(c) < 1 << 15));
+    }
+
+    #[test]
+    fn text_is_cut_off() {
+        let (width, height) = (8, 6);
+        let mut rgba = vec![0; width * height * 4];
+        draw_text(&mut rgba, width, 1, 0, "II", [255, 0, 0]);
+        let lit = |x: usize, y: usize| rgba[(y * width + x) * 4 + 3] == 255;
+
+        // I is a bar with serifs: 111 / 010 / 010 / 010 / 111
+        assert!(lit(1, 0) && lit(2, 0) && lit(3, 0));
+        assert!(!lit(1, 1) && lit(2, 1) && !lit(3, 1));
+        assert!(lit(2, 4));
+        assert!(!lit(0, 0) && !lit(4, 0) && !lit(2, 5));
+        // the second one only fits in part
+        assert!(lit(5, 0) && lit(7, 0) && lit(6, 2));
+        assert_eq!(&rgba[(width + 6) * 4..(width + 6) * 4 + 3], &[255, 0, 0]);
+    }
+}
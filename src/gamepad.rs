@@ -0,0 +1,112 @@
+use sdl2;
+use sdl2::controller::{Axis, GameController};
+use sdl2::event::Event;
+use std::collections::HashSet;
+
+// Sticks and triggers closer to rest than this do not count as pressed.
+pub static DEAD_ZONE: i16 = 8000;
+
+// Game controllers, numbered from 1 in the order they are plugged in. An
+// unplugged controller leaves a gap for the next one, so the other players
+// keep their numbers.
+//
+// Held inputs are named like keys in a keymap: `pad<n> <button>` with SDL's
+// button names (`pad1 a`, `pad1 dpup`, `pad2 start`, ...), `pad<n> <axis>-`
+// or `+` for stick directions (`pad1 lefty-` is up) and `pad<n> <trigger>`
+// for `lefttrigger` and `righttrigger`.
+pub struct Gamepads {
+    subsystem: sdl2::GameControllerSubsystem,
+    pads: Vec<Option<GameController>>,
+    held: HashSet<String>,
+}
+
+impl Gamepads {
+    // Controllers already plugged in are reported as added by the first
+    // events, like ones plugged in later.
+    pub fn new(sdl: &sdl2::Sdl) -> Result<Gamepads, String> {
+        Ok(Gamepads {
+            subsystem: sdl.game_controller()?,
+            pads: Vec::new(),
+            held: HashSet::new(),
+        })
+    }
+
+    pub fn held(&self) -> &HashSet<String> {
+        &self.held
+    }
+
+    // Updates the held inputs from a controller event.
+    pub fn handle(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => match self.subsystem.open(which) {
+                Ok(pad) => {
+                    let slot = match self.pads.iter().position(|p| p.is_none()) {
+                        Some(slot) => slot,
+                        None => {
+                            self.pads.push(None);
+                            self.pads.len() - 1
+                        }
+                    };
+                    eprintln!("Controller {}: {}", slot + 1, pad.name());
+                    self.pads[slot] = Some(pad);
+                }
+                Err(e) => eprintln!("Error Opening Controller {}", e),
+            },
+
+            Event::ControllerDeviceRemoved { which, .. } => {
+                if let Some(n) = self.number(which) {
+                    eprintln!("Controller {} removed", n);
+                    self.pads[n - 1] = None;
+                    let prefix = format!("pad{} ", n);
+                    self.held.retain(|name| !name.starts_with(&prefix));
+                }
+            }
+
+            Event::ControllerButtonDown { which, button, .. } => {
+                if let Some(n) = self.number(which) {
+                    self.held.insert(format!("pad{} {}", n, button.string()));
+                }
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                if let Some(n) = self.number(which) {
+                    self.held.remove(&format!("pad{} {}", n, button.string()));
+                }
+            }
+
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } => {
+                if let Some(n) = self.number(which) {
+                    let name = format!("pad{} {}", n, axis.string());
+                    if axis == Axis::TriggerLeft || axis == Axis::TriggerRight {
+                        self.set(name, value > DEAD_ZONE);
+                    } else {
+                        self.set(format!("{}-", name), value < -DEAD_ZONE);
+                        self.set(format!("{}+", name), value > DEAD_ZONE);
+                    }
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    fn set(&mut self, name: String, pressed: bool) {
+        if pressed {
+            self.held.insert(name);
+        } else {
+            self.held.remove(&name);
+        }
+    }
+
+    // The player number of a controller from its joystick id.
+    fn number(&self, id: i32) -> Option<usize> {
+        self.pads
+            .iter()
+            .position(|p| match *p {
+                Some(ref pad) => pad.instance_id() == id,
+                None => false,
+            })
+            .map(|slot| slot + 1)
+    }
+}
@@ -1,35 +1,19 @@
-use sdl2;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-
-static SCALE: u32 = 10;
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
 
+#[derive(Clone)]
 pub struct Gpu {
     //graphics
     gfx: [u8; 2048],
-    draw_flag: bool,
-    canvas: sdl2::render::Canvas<sdl2::video::Window>,
+    pub draw_flag: bool,
 }
 
 impl Gpu {
-    pub fn new(_sdlcontext: &sdl2::Sdl) -> Gpu {
-        let gpu = Gpu {
+    pub fn new() -> Gpu {
+        Gpu {
             draw_flag: true,
             gfx: [0; 2048],
-            canvas: _sdlcontext
-                .video()
-                .unwrap()
-                .window("rust-sdl2 demo: Video", 64 * SCALE, 32 * SCALE)
-                .position_centered()
-                .opengl()
-                .build()
-                .unwrap()
-                .into_canvas()
-                .build()
-                .unwrap(),
-        };
-
-        gpu
+        }
     }
 
     pub fn clear_screen(&mut self) {
@@ -41,15 +25,14 @@ impl Gpu {
 
     pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8]) -> u8 {
         let mut collision: u8 = 0;
-        let n = sprite.len() as usize;
 
-        for j in 0..n {
+        for (j, row) in sprite.iter().enumerate() {
             for i in 0..8 {
-                if (sprite[j] & (0x80 >> i)) != 0 {
+                if (row & (0x80 >> i)) != 0 {
                     let mut z = x + i + ((y + j) * 64);
                     // because the value must be able to overflow
                     if z >= 2048 {
-                        z = z % 2048;
+                        z %= 2048;
                     }
 
                     if self.gfx[z] == 1 {
@@ -65,28 +48,40 @@ impl Gpu {
         collision
     }
 
-    pub fn render_screen(&mut self) {
-        if !self.draw_flag {
-            return;
-        }
-
-        //first clear screen
-        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
-        self.canvas.clear();
-
-        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+    // Like draw_sprite, but the sprite's position wraps around the screen
+    // and the parts of it past the edges are not drawn.
+    pub fn draw_sprite_clipped(&mut self, x: usize, y: usize, sprite: &[u8]) -> u8 {
+        let (x, y) = (x % WIDTH, y % HEIGHT);
+        let mut collision: u8 = 0;
 
-        for i in 0..32 {
-            for j in 0..64 {
-                if self.gfx[i * 64 + j] == 1 {
-                    let x = (j as i32) * (SCALE as i32);
-                    let y = (i as i32) * (SCALE as i32);
-                    self.canvas.fill_rect(Rect::new(x, y, SCALE, SCALE)).ok();
+        for (j, row) in sprite.iter().enumerate().take(HEIGHT - y) {
+            for i in 0..8.min(WIDTH - x) {
+                if (row & (0x80 >> i)) != 0 {
+                    let z = x + i + (y + j) * WIDTH;
+                    if self.gfx[z] == 1 {
+                        collision = 1;
+                    }
+                    self.gfx[z] ^= 1;
                 }
             }
         }
-        self.canvas.present();
 
-        self.draw_flag = false;
+        self.draw_flag = true;
+
+        collision
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.gfx
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.gfx[y * WIDTH + x] == 1
+    }
+}
+
+impl Default for Gpu {
+    fn default() -> Gpu {
+        Gpu::new()
     }
 }
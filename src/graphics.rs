@@ -0,0 +1,233 @@
+use gpu::{Gpu, HEIGHT, WIDTH};
+use palette::Palette;
+use std::env;
+use std::io;
+use std::io::prelude::*;
+
+// Terminal graphics protocols that can show the framebuffer as real pixels.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Protocol {
+    Sixel,
+    Kitty,
+}
+
+impl Protocol {
+    pub fn parse(s: &str) -> Option<Protocol> {
+        match s {
+            "sixel" => Some(Protocol::Sixel),
+            "kitty" => Some(Protocol::Kitty),
+            _ => None,
+        }
+    }
+
+    // Guesses from the environment; terminals cannot be asked without
+    // reading their reply from stdin.
+    pub fn detect() -> Option<Protocol> {
+        let term = env::var("TERM").unwrap_or_default();
+        let program = env::var("TERM_PROGRAM").unwrap_or_default();
+        if env::var("KITTY_WINDOW_ID").is_ok()
+            || term.contains("kitty")
+            || term.contains("ghostty")
+            || program == "WezTerm"
+            || program == "ghostty"
+        {
+            Some(Protocol::Kitty)
+        } else if ["foot", "mlterm", "contour", "yaft", "sixel"]
+            .iter()
+            .any(|t| term.contains(t))
+            || program == "iTerm.app"
+        {
+            Some(Protocol::Sixel)
+        } else {
+            None
+        }
+    }
+
+    // Writes the framebuffer scaled up by `scale` at the cursor.
+    pub fn write<W: Write>(
+        self,
+        gpu: &Gpu,
+        scale: usize,
+        palette: &Palette,
+        w: &mut W,
+    ) -> io::Result<()> {
+        match self {
+            Protocol::Sixel => write_sixel(gpu, scale, palette, w),
+            Protocol::Kitty => write_kitty(gpu, scale, palette, w),
+        }
+    }
+}
+
+// DEC Sixel: bands of six rows, one pass per colour, run-length encoded.
+pub fn write_sixel<W: Write>(
+    gpu: &Gpu,
+    scale: usize,
+    palette: &Palette,
+    w: &mut W,
+) -> io::Result<()> {
+    let (width, height) = (WIDTH * scale, HEIGHT * scale);
+    let mut out = Vec::new();
+
+    // 1:1 pixel aspect, background drawn rather than left transparent
+    write!(out, "\x1bP0;1;0q\"1;1;{};{}", width, height)?;
+    for c in 0..4u8 {
+        let [r, g, b] = palette.colour(c);
+        let percent = |v: u8| v as u32 * 100 / 255;
+        write!(out, "#{};2;{};{};{}", c, percent(r), percent(g), percent(b))?;
+    }
+
+    for band in 0..height.div_ceil(6) {
+        let mut first = true;
+        for c in 0..4u8 {
+            let sixels: Vec<u8> = (0..width)
+                .map(|x| {
+                    let mut bits = 0;
+                    for dy in 0..6 {
+                        let y = band * 6 + dy;
+                        if y < height && gpu.pixels()[(y / scale) * WIDTH + x / scale] & 3 == c {
+                            bits |= 1 << dy;
+                        }
+                    }
+                    bits
+                })
+                .collect();
+            if sixels.iter().all(|&s| s == 0) {
+                continue;
+            }
+
+            if !first {
+                out.push(b'$');
+            }
+            first = false;
+            write!(out, "#{}", c)?;
+            for run in sixels.chunk_by(|a, b| a == b) {
+                let ch = b'?' + run[0];
+                if run.len() > 3 {
+                    write!(out, "!{}", run.len())?;
+                    out.push(ch);
+                } else {
+                    out.extend(run.iter().map(|_| ch));
+                }
+            }
+        }
+        out.push(b'-');
+    }
+    out.extend_from_slice(b"\x1b\\");
+
+    w.write_all(&out)
+}
+
+// Base64 is sent in chunks of at most this many bytes.
+const KITTY_CHUNK: usize = 4096;
+
+// Kitty graphics protocol: raw RGB, base64 encoded. The image always has
+// the same id, so each frame replaces the one before.
+pub fn write_kitty<W: Write>(
+    gpu: &Gpu,
+    scale: usize,
+    palette: &Palette,
+    w: &mut W,
+) -> io::Result<()> {
+    let (width, height) = (WIDTH * scale, HEIGHT * scale);
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let p = gpu.pixels()[(y / scale) * WIDTH + x / scale];
+            rgb.extend_from_slice(&palette.colour(p));
+        }
+    }
+
+    let data = base64(&rgb);
+    let chunks: Vec<&[u8]> = data.chunks(KITTY_CHUNK).collect();
+    for (n, chunk) in chunks.iter().enumerate() {
+        let more = (n + 1 < chunks.len()) as u8;
+        if n == 0 {
+            // q=2 keeps the terminal from replying, C=1 the cursor still
+            write!(
+                w,
+                "\x1b_Ga=T,f=24,s={},v={},i=1,q=2,C=1,m={};",
+                width, height, more
+            )?;
+        } else {
+            write!(w, "\x1b_Gm={};", more)?;
+        }
+        w.write_all(chunk)?;
+        w.write_all(b"\x1b\\")?;
+    }
+    Ok(())
+}
+
+static BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len().div_ceil(3) * 4);
+    for group in data.chunks(3) {
+        let n = group
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= group.len() {
+                out.push(BASE64[(n >> (18 - 6 * i)) as usize & 63]);
+            } else {
+                out.push(b'=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a blank screen but for the top left pixel, in black and white
+    fn screen() -> (Gpu, Palette) {
+        let mut gpu = Gpu::new();
+        gpu.draw_sprite(0, 0, &[0x80]);
+        (gpu, Palette::parse("000000,ffffff").unwrap())
+    }
+
+    #[test]
+    fn sixel() {
+        let (gpu, palette) = screen();
+        let mut out = Vec::new();
+        write_sixel(&gpu, 1, &palette, &mut out).unwrap();
+        let expected = concat!(
+            "\x1bP0;1;0q\"1;1;64;32",
+            "#0;2;0;0;0#1;2;100;100;100#2;2;100;100;100#3;2;100;100;100",
+            "#0}!63~$#1@!63?-",
+            "#0!64~-#0!64~-#0!64~-#0!64~-",
+            // the last band has two rows
+            "#0!64B-",
+            "\x1b\\"
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn kitty() {
+        let (gpu, palette) = screen();
+        let mut out = Vec::new();
+        write_kitty(&gpu, 1, &palette, &mut out).unwrap();
+        // 64 x 32 x 3 bytes make exactly two chunks of base64
+        let expected = format!(
+            "\x1b_Ga=T,f=24,s=64,v=32,i=1,q=2,C=1,m=1;////{}\x1b\\\x1b_Gm=0;{}\x1b\\",
+            "A".repeat(KITTY_CHUNK - 4),
+            "A".repeat(KITTY_CHUNK)
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn base64_padding() {
+        let encode = |s: &str| String::from_utf8(base64(s.as_bytes())).unwrap();
+        assert_eq!(encode(""), "");
+        assert_eq!(encode("f"), "Zg==");
+        assert_eq!(encode("fo"), "Zm8=");
+        assert_eq!(encode("foo"), "Zm9v");
+        assert_eq!(encode("foob"), "Zm9vYg==");
+        assert_eq!(encode("fooba"), "Zm9vYmE=");
+        assert_eq!(encode("foobar"), "Zm9vYmFy");
+    }
+}
@@ -0,0 +1,130 @@
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+// Which keyboard keys press which keypad buttons, loaded from files where
+// every line is
+//
+//     <button> = <key>[, <key>...]
+//
+// with the button as a hex digit and keys by their SDL names (`Q`, `4`,
+// `Keypad 4`, `Space`, ...) or controller inputs (`pad1 a`, `pad2 lefty-`,
+// see `gamepad`), in any case. Buttons a file leaves out keep their keys.
+// Blank lines and lines starting with `#` are ignored.
+pub struct Keymap {
+    buttons: [Vec<String>; 16],
+}
+
+// The COSMAC VIP keypad, on the left of a QWERTY keyboard:
+//
+//     1 2 3 C      1 2 3 4
+//     4 5 6 D      Q W E R
+//     7 8 9 E      A S D F
+//     A 0 B F      Z X C V
+//
+// The first controller's d-pad and left stick press 2 4 6 8 and its A
+// button 5, which many games use for movement and fire.
+static LAYOUT: [&[&str]; 16] = [
+    &["X"],
+    &["1"],
+    &["2", "pad1 dpup", "pad1 lefty-"],
+    &["3"],
+    &["Q", "pad1 dpleft", "pad1 leftx-"],
+    &["W", "pad1 a"],
+    &["E", "pad1 dpright", "pad1 leftx+"],
+    &["A"],
+    &["S", "pad1 dpdown", "pad1 lefty+"],
+    &["D"],
+    &["Z"],
+    &["C"],
+    &["4"],
+    &["R"],
+    &["F"],
+    &["V"],
+];
+
+impl Keymap {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Keymap> {
+        let mut keymap = Keymap::default();
+        keymap.apply_file(path)?;
+        Ok(keymap)
+    }
+
+    // The standard layout, changed by `config` and then by `<rom>.keys`
+    // next to the ROM, e.g. `games/pong2.keys`, when they exist.
+    pub fn load_for_rom<P: AsRef<Path>, R: AsRef<Path>>(config: P, rom: R) -> Keymap {
+        let mut keymap = Keymap::default();
+        for path in &[
+            config.as_ref().to_path_buf(),
+            rom.as_ref().with_extension("keys"),
+        ] {
+            if !path.exists() {
+                continue;
+            }
+            if let Err(e) = keymap.apply_file(path) {
+                eprintln!("Error Reading {}: {}", path.display(), e);
+            }
+        }
+        keymap
+    }
+
+    pub fn apply_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let mut s = String::new();
+        File::open(path)?.read_to_string(&mut s)?;
+        self.apply(&s)
+    }
+
+    // Replaces the keys of every button in `s`.
+    pub fn apply(&mut self, s: &str) -> io::Result<()> {
+        for (n, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let button = parts
+                .next()
+                .and_then(|b| usize::from_str_radix(b.trim(), 16).ok())
+                .filter(|&b| b < 16);
+            let (button, keys) = match (button, parts.next()) {
+                (Some(button), Some(keys)) => (button, keys),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("bad key mapping on line {}: {}", n + 1, line),
+                    ))
+                }
+            };
+
+            self.set(button, keys.split(',').map(|k| k.trim()));
+        }
+        Ok(())
+    }
+
+    // Replaces the keys of a button.
+    pub fn set<I: IntoIterator<Item = S>, S: AsRef<str>>(&mut self, button: usize, keys: I) {
+        self.buttons[button] = keys
+            .into_iter()
+            .map(|k| k.as_ref().to_lowercase())
+            .filter(|k| !k.is_empty())
+            .collect();
+    }
+
+    // The button a key presses, if any.
+    pub fn button(&self, key: &str) -> Option<usize> {
+        let key = key.to_lowercase();
+        self.buttons.iter().position(|keys| keys.contains(&key))
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let mut buttons: [Vec<String>; 16] = Default::default();
+        for (keys, layout) in buttons.iter_mut().zip(LAYOUT.iter()) {
+            *keys = layout.iter().map(|k| k.to_lowercase()).collect();
+        }
+        Keymap { buttons }
+    }
+}
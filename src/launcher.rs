@@ -0,0 +1,329 @@
+use config;
+use font::{self, CELL_HEIGHT, CELL_WIDTH};
+use gpu::{Gpu, HEIGHT, WIDTH};
+use palette::Palette;
+use rom;
+use romdb::{RomDb, RomInfo};
+use screenshot;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+// The launcher's screen, four times the CHIP-8 one.
+pub static SCREEN_WIDTH: usize = 256;
+pub static SCREEN_HEIGHT: usize = 128;
+
+// Where the last frame of each game is kept as a PBM, by the SHA-1 of the
+// ROM, out of its archive if it came in one.
+pub static THUMBNAILS: &str = "thumbnails";
+
+// List rows on the screen, below the heading and above the help.
+static ROWS: usize = 18;
+// Characters that fit in the list, left of the thumbnail.
+static COLUMNS: usize = 29;
+
+pub struct Entry {
+    pub path: PathBuf,
+    pub title: String,
+    pub info: Option<RomInfo>,
+    // the pixels of the last frame played, if it was
+    pub thumbnail: Option<Vec<u8>>,
+}
+
+// A list of the ROMs in some folders to pick one from.
+pub struct Launcher {
+    heading: String,
+    entries: Vec<Entry>,
+    selected: usize,
+    // the first entry shown
+    top: usize,
+}
+
+impl Launcher {
+    // Lists the ROMs and zip archives in `folders`, by title. Titles come
+    // from the ROM database, or else the file names.
+    pub fn scan<P: AsRef<Path>>(folders: &[P], db: &RomDb) -> Launcher {
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for folder in folders {
+            let files = match fs::read_dir(folder) {
+                Ok(files) => files,
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    eprintln!("Error Reading {}: {}", folder.as_ref().display(), e);
+                    continue;
+                }
+            };
+            for file in files.filter_map(|f| f.ok()) {
+                let path = file.path();
+                if rom::is_rom_file(&path) && !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+
+        let mut launcher = Launcher::list("CHIP-8", &paths, db);
+        launcher.entries.sort_by_key(|e| e.title.to_lowercase());
+        launcher
+    }
+
+    // Lists `paths` in their order, like the recently played ROMs. Files
+    // that cannot be read any more are left out.
+    pub fn list<P: AsRef<Path>>(heading: &str, paths: &[P], db: &RomDb) -> Launcher {
+        let mut entries = Vec::new();
+        for path in paths {
+            let path = path.as_ref().to_path_buf();
+            let data = match fs::read(&path) {
+                Ok(data) => data,
+                Err(_) => continue,
+            };
+            // archives that cannot be unpacked are still listed, and say
+            // what is wrong when played
+            let hash = rom::unpack(data).ok().map(|r| config::rom_hash(&r.data));
+            let info = hash.as_ref().and_then(|h| db.get(h)).cloned();
+            let title = match info {
+                Some(ref info) => info.title.clone(),
+                None => path
+                    .file_stem()
+                    .map_or_else(|| path.to_string_lossy(), |s| s.to_string_lossy())
+                    .into_owned(),
+            };
+            entries.push(Entry {
+                thumbnail: hash.and_then(|h| load_thumbnail(&h)),
+                path,
+                title,
+                info,
+            });
+        }
+
+        Launcher {
+            heading: heading.to_uppercase(),
+            entries,
+            selected: 0,
+            top: 0,
+        }
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn selected(&self) -> Option<&Entry> {
+        self.entries.get(self.selected)
+    }
+
+    // Moves the selection by `rows`, up for negative ones, stopping at the
+    // ends of the list.
+    pub fn move_by(&mut self, rows: isize) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let last = self.entries.len() as isize - 1;
+        self.selected = (self.selected as isize + rows).clamp(0, last) as usize;
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + ROWS {
+            self.top = self.selected + 1 - ROWS;
+        }
+    }
+
+    // Draws the list with the thumbnail and details of the selected ROM.
+    pub fn render(&self, palette: &Palette, rgba: &mut Vec<u8>) {
+        let (back, fore) = (palette.background(), palette.colour(1));
+        rgba.clear();
+        for _ in 0..SCREEN_WIDTH * SCREEN_HEIGHT {
+            rgba.extend_from_slice(&[back[0], back[1], back[2], 255]);
+        }
+        let text = |rgba: &mut Vec<u8>, column: usize, row: usize, s: &str, colour| {
+            font::draw_text(
+                rgba,
+                SCREEN_WIDTH,
+                2 + column * CELL_WIDTH,
+                2 + row * CELL_HEIGHT,
+                s,
+                colour,
+            )
+        };
+
+        text(rgba, 0, 0, &self.heading, fore);
+        let count = format!("{} ROMS", self.entries.len());
+        text(rgba, COLUMNS - count.len(), 0, &count, fore);
+        text(rgba, 0, 20, "ENTER PLAY   TAB ALL/RECENT   ESC QUIT", fore);
+        if self.entries.is_empty() {
+            text(rgba, 0, 2, "NO ROMS FOUND", fore);
+            return;
+        }
+
+        let shown = self.entries.iter().enumerate().skip(self.top).take(ROWS);
+        for (row, (i, entry)) in (2..).zip(shown) {
+            let title: String = entry.title.chars().take(COLUMNS - 2).collect();
+            if i == self.selected {
+                fill(
+                    rgba,
+                    0,
+                    1 + row * CELL_HEIGHT,
+                    COLUMNS * CELL_WIDTH + 3,
+                    CELL_HEIGHT + 1,
+                    fore,
+                );
+                text(rgba, 0, row, &format!("> {}", title), back);
+            } else {
+                text(rgba, 2, row, &title, fore);
+            }
+        }
+
+        // the selected ROM on the right: the thumbnail at twice the size,
+        // then the author and file name
+        let entry = &self.entries[self.selected];
+        let (x, y) = (SCREEN_WIDTH - WIDTH * 2 - 2, 2 + 2 * CELL_HEIGHT);
+        match entry.thumbnail {
+            Some(ref pixels) => {
+                for (i, &p) in pixels.iter().enumerate() {
+                    let colour = palette.colour(p);
+                    fill(rgba, x + i % WIDTH * 2, y + i / WIDTH * 2, 2, 2, colour);
+                }
+            }
+            None => {
+                frame(rgba, x, y, WIDTH * 2, HEIGHT * 2, fore);
+                font::draw_text(rgba, SCREEN_WIDTH, x + 36, y + 29, "NOT PLAYED YET", fore);
+            }
+        }
+
+        let column = (x - 2) / CELL_WIDTH;
+        let mut details = Vec::new();
+        if let Some(ref info) = entry.info {
+            if let Some(ref author) = info.author {
+                details.push(format!("BY {}", author));
+            }
+        }
+        let path = &entry.path;
+        details.push(
+            path.file_name()
+                .map_or_else(|| path.to_string_lossy(), |s| s.to_string_lossy())
+                .into_owned(),
+        );
+        for (row, line) in (14..).zip(details) {
+            let line: String = line.chars().take(WIDTH * 2 / CELL_WIDTH).collect();
+            text(rgba, column, row, &line, fore);
+        }
+    }
+}
+
+// Keeps the last frame of a game to show in the launcher, for the ROM as
+// loaded like Cpu::rom.
+pub fn save_thumbnail(rom: &[u8], gpu: &Gpu) -> io::Result<()> {
+    let hash = config::rom_hash(rom);
+    fs::create_dir_all(THUMBNAILS)?;
+    let mut f = File::create(Path::new(THUMBNAILS).join(hash + ".pbm"))?;
+    screenshot::write_pbm(gpu, &mut f)
+}
+
+// The pixels in a thumbnail written by save_thumbnail.
+fn load_thumbnail(hash: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    File::open(Path::new(THUMBNAILS).join(format!("{}.pbm", hash)))
+        .and_then(|mut f| f.read_to_end(&mut data))
+        .ok()?;
+    parse_thumbnail(&data)
+}
+
+// Only PBMs at the native resolution as write_pbm makes them will do.
+fn parse_thumbnail(data: &[u8]) -> Option<Vec<u8>> {
+    let header = format!("P4\n{} {}\n", WIDTH, HEIGHT);
+    let bits = data.get(header.len()..)?;
+    if !data.starts_with(header.as_bytes()) || bits.len() < WIDTH * HEIGHT / 8 {
+        return None;
+    }
+    Some(
+        (0..WIDTH * HEIGHT)
+            .map(|i| (bits[i / 8] >> (7 - i % 8)) & 1)
+            .collect(),
+    )
+}
+
+fn fill(rgba: &mut [u8], x: usize, y: usize, w: usize, h: usize, colour: [u8; 3]) {
+    for py in y..(y + h).min(SCREEN_HEIGHT) {
+        for px in x..(x + w).min(SCREEN_WIDTH) {
+            let p = (py * SCREEN_WIDTH + px) * 4;
+            rgba[p..p + 3].copy_from_slice(&colour);
+        }
+    }
+}
+
+// A one pixel outline.
+fn frame(rgba: &mut [u8], x: usize, y: usize, w: usize, h: usize, colour: [u8; 3]) {
+    fill(rgba, x, y, w, 1, colour);
+    fill(rgba, x, y + h - 1, w, 1, colour);
+    fill(rgba, x, y, 1, h, colour);
+    fill(rgba, x + w - 1, y, 1, h, colour);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn launcher(count: usize) -> Launcher {
+        let entries = (0..count)
+            .map(|i| Entry {
+                path: PathBuf::from(format!("{}.ch8", i)),
+                title: i.to_string(),
+                info: None,
+                thumbnail: None,
+            })
+            .collect();
+        Launcher {
+            heading: "TEST".to_string(),
+            entries,
+            selected: 0,
+            top: 0,
+        }
+    }
+
+    #[test]
+    fn moving_scrolls_the_list() {
+        let mut l = launcher(30);
+        l.move_by(-1);
+        assert_eq!((l.selected, l.top), (0, 0));
+        l.move_by(ROWS as isize - 1);
+        assert_eq!((l.selected, l.top), (17, 0));
+        l.move_by(1);
+        assert_eq!((l.selected, l.top), (18, 1));
+        l.move_by(100);
+        assert_eq!((l.selected, l.top), (29, 12));
+        l.move_by(-20);
+        assert_eq!((l.selected, l.top), (9, 9));
+        assert_eq!(l.selected().unwrap().title, "9");
+
+        let mut empty = launcher(0);
+        empty.move_by(1);
+        assert!(empty.selected().is_none());
+    }
+
+    #[test]
+    fn thumbnails_read_back() {
+        let mut gpu = Gpu::new();
+        gpu.draw_sprite(0, 0, &[0xA0]);
+        gpu.draw_sprite(WIDTH - 8, HEIGHT - 1, &[0x01]);
+        let mut pbm = Vec::new();
+        screenshot::write_pbm(&gpu, &mut pbm).unwrap();
+
+        assert_eq!(parse_thumbnail(&pbm).unwrap(), gpu.pixels());
+    }
+
+    #[test]
+    fn broken_thumbnails_ignored() {
+        let mut pbm = Vec::new();
+        screenshot::write_pbm(&Gpu::new(), &mut pbm).unwrap();
+
+        // cut short
+        assert!(parse_thumbnail(&pbm[..pbm.len() - 1]).is_none());
+        assert!(parse_thumbnail(&pbm[..4]).is_none());
+        assert!(parse_thumbnail(&[]).is_none());
+        // another size, or a plain text PBM
+        let mut large = b"P4\n128 64\n".to_vec();
+        large.extend_from_slice(&[0; 128 * 64 / 8]);
+        assert!(parse_thumbnail(&large).is_none());
+        let mut text = b"P1\n64 32\n".to_vec();
+        text.extend_from_slice(&[b'0'; 64 * 32]);
+        assert!(parse_thumbnail(&text).is_none());
+    }
+}
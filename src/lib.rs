@@ -0,0 +1,42 @@
+extern crate crc32fast;
+extern crate gif;
+extern crate png;
+extern crate rand;
+extern crate serde_json;
+extern crate sha1_smol;
+extern crate toml_edit;
+extern crate zip;
+#[cfg(feature = "sdl")]
+extern crate sdl2;
+
+pub mod config;
+pub mod control;
+pub mod coverage;
+pub mod cpu;
+pub mod crt;
+pub mod debugger;
+pub mod disasm;
+#[cfg(feature = "sdl")]
+pub mod display;
+pub mod filter;
+pub mod font;
+#[cfg(feature = "sdl")]
+pub mod gamepad;
+pub mod gpu;
+pub mod graphics;
+pub mod keymap;
+pub mod launcher;
+pub mod octo;
+pub mod options;
+pub mod osd;
+pub mod palette;
+pub mod patch;
+pub mod quirks;
+pub mod recent;
+pub mod recorder;
+pub mod rom;
+pub mod romdb;
+pub mod screenshot;
+pub mod symbols;
+pub mod trace;
+pub mod tui;
@@ -1,192 +1,779 @@
-extern crate rand;
+extern crate chip8;
 extern crate sdl2;
 
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::controller::Button;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, LSHIFTMOD, RSHIFTMOD};
+use sdl2::EventPump;
+use std::collections::HashSet;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::process;
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use cpu::Cpu;
-
-mod cpu;
-mod gpu;
+use chip8::config::{self, Profile};
+use chip8::control::{self, Control};
+use chip8::cpu::Cpu;
+use chip8::debugger::Debugger;
+use chip8::display::{self, Display};
+use chip8::gamepad::Gamepads;
+use chip8::gpu::Gpu;
+use chip8::keymap::Keymap;
+use chip8::launcher::{self, Launcher};
+use chip8::options::{self, Options};
+use chip8::osd::Osd;
+use chip8::palette::Palette;
+use chip8::recent::{self, Recent};
+use chip8::recorder::{self, GifRecorder, RawRecorder, Recorder};
+use chip8::rom;
+use chip8::screenshot;
+use chip8::symbols::Symbols;
 
 fn main() {
-    // Setup Graphics
-    let sdl_context = sdl2::init().unwrap();
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {}\nTry `chip8 --help` for the options.", e);
+            process::exit(2);
+        }
+    };
+    if options.help {
+        print!("{}", options::USAGE);
+        return;
+    }
+
+    // a ROM on the command line is played straight away, otherwise the
+    // window starts with the launcher
+    let mut cpu = Cpu::new();
+    let game = if options.rom.is_some() || options.headless {
+        match start(&mut cpu, &options, options.rom()) {
+            Ok(game) => Some(game),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                process::exit(2);
+            }
+        }
+    } else {
+        None
+    };
+
+    // --raw-video streams raw RGB frames to stdout
+    let mut recorder: Option<Box<dyn Recorder>> = None;
+    if options.raw_video {
+        let palette = game
+            .as_ref()
+            .map_or(&options, |g| &g.options)
+            .palette
+            .clone();
+        recorder = Some(Box::new(RawRecorder::new(
+            io::stdout(),
+            options.scale.unwrap_or(display::SCALE) as usize,
+            &palette.unwrap_or_default(),
+        )));
+    }
+
+    match game {
+        Some(mut game) if options.headless => {
+            run_headless(&mut cpu, &mut game, &mut recorder);
+            finish(&cpu, &game);
+        }
+        game => run_window(&mut cpu, game, &options, &mut recorder),
+    }
+
+    if let Some(mut r) = recorder {
+        if let Err(e) = r.finish() {
+            eprintln!("Error Writing Recording {}", e);
+        }
+    }
+
+    // the window goes back to the launcher instead
+    if options.headless {
+        if let Some(e) = cpu.error() {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// A ROM being played, with its settings.
+struct Game {
+    rom: String,
+    data: Vec<u8>,
+    options: Options,
+    debugger: Debugger,
+}
+
+// Starts `rom` over on the Cpu with the settings from the command line,
+// the config file, Octo options and the ROM database.
+fn start(cpu: &mut Cpu, options: &Options, rom: &str) -> Result<Game, String> {
+    let mut options = options.clone();
+    options.rom = Some(rom.to_string());
+    let r = options
+        .read_rom()
+        .map_err(|e| format!("cannot read the ROM `{}`: {}", rom, e))?;
+    options.load_config(&r.data);
+    options.merge_octo_options(r.profile);
 
-    //set up cpu
-    let mut cpu = Cpu::new(&sdl_context);
+    *cpu = Cpu::new();
     cpu.gpu.clear_screen();
-    cpu.load_game("./games/pong2.c8".to_string());
+    options
+        .load_rom(cpu, &r.data)
+        .map_err(|e| format!("cannot load the ROM `{}`: {}", rom, e))?;
+    if let Some(ref info) = cpu.rom_info {
+        for line in info.describe() {
+            eprintln!("{}", line);
+        }
+    }
+
+    let mut debugger = Debugger::new(Symbols::load_for_rom(rom));
+    if options.raw_video {
+        debugger.set_output(Box::new(io::stderr()));
+    }
+    if let Some(ref path) = options.trace {
+        match File::create(path) {
+            Ok(f) => debugger.set_trace(Box::new(BufWriter::new(f))),
+            Err(e) => eprintln!("Error Writing {}: {}", path, e),
+        }
+    }
+    if options.debug {
+        debugger.pause();
+    }
+    Ok(Game {
+        rom: rom.to_string(),
+        data: r.data,
+        options,
+        debugger,
+    })
+}
 
+// --coverage <prefix> dumps the coverage of this run, merged with any
+// previous runs that used the same prefix
+fn finish(cpu: &Cpu, game: &Game) {
+    if let Some(ref prefix) = game.options.coverage {
+        if let Err(e) = cpu
+            .coverage
+            .save(prefix, cpu.rom(), cpu.quirks(), game.debugger.symbols())
+        {
+            eprintln!("Error Writing Coverage {}", e);
+        }
+    }
+}
+
+// Runs as fast as possible, with no window and no input.
+fn run_headless(cpu: &mut Cpu, game: &mut Game, recorder: &mut Option<Box<dyn Recorder>>) {
+    while game.options.frames.is_none_or(|n| cpu.frames() < n) {
+        let frame = cpu.frames();
+        if !game.debugger.run_cycle(cpu) {
+            break;
+        }
+        if cpu.frames() != frame && !capture(recorder, &cpu.gpu) {
+            break;
+        }
+    }
+}
+
+// Plays `game`, or the ones picked in the launcher, until the window is
+// closed. Esc goes back to the launcher.
+fn run_window(
+    cpu: &mut Cpu,
+    mut game: Option<Game>,
+    options: &Options,
+    recorder: &mut Option<Box<dyn Recorder>>,
+) {
+    let sdl_context = sdl2::init().unwrap();
+
+    // controllers are optional, the keyboard always works
+    let mut gamepads = match Gamepads::new(&sdl_context) {
+        Ok(pads) => Some(pads),
+        Err(e) => {
+            eprintln!("Error Opening Controllers {}", e);
+            None
+        }
+    };
+
+    let scale = options.scale.unwrap_or(display::SCALE);
+    let mut display = Display::new(&sdl_context, "chip8", scale);
+    if let Some(ref palette) = options.palette {
+        display.set_palette(&mut cpu.gpu, palette.clone());
+    }
     let mut event_pump = sdl_context.event_pump().unwrap();
-    let mut start;
-    let wait = Duration::from_millis(1000 / 300);
+    let mut recent = Recent::load(recent::RECENT);
+    let mut osd = Osd::new(options.osd.unwrap_or_default());
+
+    // the CLI's game, or else the launcher
+    let mut next = Next::Launcher(false);
+    loop {
+        let rom = match game.take() {
+            Some(game) => Ok(game),
+            None => match next {
+                Next::Load(rom) => start(cpu, options, &rom),
+                Next::Launcher(show_recent) => {
+                    let pick = choose(
+                        cpu,
+                        options,
+                        &recent,
+                        show_recent,
+                        &mut display,
+                        &mut event_pump,
+                        &mut gamepads,
+                        &mut osd,
+                    );
+                    match pick {
+                        Some(rom) => start(cpu, options, &rom),
+                        None => break,
+                    }
+                }
+                Next::Quit => break,
+            },
+        };
+        let mut current = match rom {
+            Ok(game) => game,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                next = Next::Launcher(false);
+                continue;
+            }
+        };
+        recent.add(&current.rom);
+        if let Err(e) = recent.save() {
+            eprintln!("Error Writing {}: {}", recent::RECENT, e);
+        }
+
+        next = play(
+            cpu,
+            &mut current,
+            &mut display,
+            &mut event_pump,
+            &mut gamepads,
+            &mut osd,
+            recorder,
+        );
+        if let Err(e) = launcher::save_thumbnail(cpu.rom(), &cpu.gpu) {
+            eprintln!("Error Writing Thumbnail {}", e);
+        }
+        finish(cpu, &current);
+    }
+}
+
+// What the window does once a game stops.
+enum Next {
+    // a ROM dropped on the window
+    Load(String),
+    // the launcher, with the recent ROMs when true
+    Launcher(bool),
+    Quit,
+}
+
+// Shows the launcher until a ROM is picked or dropped on the window, or
+// None when the window is closed. Tab switches between all the ROMs and the
+// recent ones.
+#[allow(clippy::too_many_arguments)]
+fn choose(
+    cpu: &mut Cpu,
+    options: &Options,
+    recent: &Recent,
+    mut show_recent: bool,
+    display: &mut Display,
+    event_pump: &mut EventPump,
+    gamepads: &mut Option<Gamepads>,
+    osd: &mut Osd,
+) -> Option<String> {
+    display.set_title("chip8");
+    let db = options.romdb();
+    let list = |show_recent| {
+        if show_recent {
+            Launcher::list("Recent", recent.roms(), &db)
+        } else {
+            Launcher::scan(&options.folders(), &db)
+        }
+    };
+    let mut launcher = list(show_recent);
+    let wait = Duration::from_nanos(1_000_000_000 / recorder::FPS);
 
-    'event: loop {
-        start = Instant::now();
+    loop {
+        let start = Instant::now();
+        let mut pick = false;
         for event in event_pump.poll_iter() {
             match event {
-                Event::Quit { .. } => break 'event,
-
-                // ---- KEY DOWN ----
-                // 1234
+                Event::Quit { .. } => return None,
+                // anything but a ROM is only reported
+                Event::DropFile { filename, .. } if dropped(osd, &filename) => {
+                    return Some(filename)
+                }
                 Event::KeyDown {
-                    keycode: Some(Keycode::Num1),
+                    keycode: Some(key), ..
+                } => match key {
+                    Keycode::Escape => return None,
+                    Keycode::Up => launcher.move_by(-1),
+                    Keycode::Down => launcher.move_by(1),
+                    Keycode::PageUp => launcher.move_by(-10),
+                    Keycode::PageDown => launcher.move_by(10),
+                    Keycode::Home => launcher.move_by(-(launcher.entries().len() as isize)),
+                    Keycode::End => launcher.move_by(launcher.entries().len() as isize),
+                    Keycode::Tab => {
+                        show_recent = !show_recent;
+                        launcher = list(show_recent);
+                    }
+                    Keycode::Return | Keycode::KpEnter | Keycode::Space => pick = true,
+                    Keycode::F10 => display.toggle_fullscreen(&mut cpu.gpu),
+                    _ => {}
+                },
+
+                Event::ControllerDeviceAdded { .. }
+                | Event::ControllerDeviceRemoved { .. }
+                | Event::ControllerButtonDown { .. }
+                | Event::ControllerButtonUp { .. }
+                | Event::ControllerAxisMotion { .. } => {
+                    if let Event::ControllerButtonDown { button, .. } = event {
+                        match button {
+                            Button::DPadUp => launcher.move_by(-1),
+                            Button::DPadDown => launcher.move_by(1),
+                            Button::LeftShoulder => launcher.move_by(-10),
+                            Button::RightShoulder => launcher.move_by(10),
+                            Button::A | Button::Start => pick = true,
+                            _ => {}
+                        }
+                    }
+                    if let Some(ref mut pads) = *gamepads {
+                        pads.handle(&event);
+                    }
+                }
+
+                _ => {}
+            }
+        }
+        if pick {
+            if let Some(entry) = launcher.selected() {
+                return Some(entry.path.to_string_lossy().into_owned());
+            }
+        }
+
+        display.render_launcher(&launcher, osd);
+
+        let elapsed = start.elapsed();
+        if wait > elapsed {
+            thread::sleep(wait - elapsed);
+        }
+    }
+}
+
+// Runs the game in the window until Esc or F4 asks for the launcher, a ROM
+// is dropped on the window or it is time to quit.
+fn play(
+    cpu: &mut Cpu,
+    game: &mut Game,
+    display: &mut Display,
+    event_pump: &mut EventPump,
+    gamepads: &mut Option<Gamepads>,
+    osd: &mut Osd,
+    recorder: &mut Option<Box<dyn Recorder>>,
+) -> Next {
+    let rom = game.rom.as_str();
+    let data = &game.data;
+    let options = &game.options;
+    let debugger = &mut game.debugger;
+
+    let config = options.keymap.as_ref().map_or("keymap.cfg", |k| k.as_str());
+    let mut keymap = Keymap::load_for_rom(config, rom);
+    for &(button, ref keys) in &options.keys {
+        keymap.set(button, keys);
+    }
+    let mut held = HashSet::new();
+
+    let name = match cpu.rom_info {
+        Some(ref info) => info.title.clone(),
+        None => Path::new(rom)
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned(),
+    };
+    if options.title != Some(false) {
+        display.set_title(&format!("{} - chip8", name));
+    }
+    osd.set_rom(&name);
+    let scale = options.scale.unwrap_or(display::SCALE);
+
+    let mut palettes = Palette::builtin();
+    if let Some(ref palette) = options.palette {
+        if !palettes.contains(palette) {
+            palettes.push(palette.clone());
+        }
+        display.set_palette(&mut cpu.gpu, palette.clone());
+    }
+    if let Some(crt) = options.crt {
+        display.set_crt(&mut cpu.gpu, crt);
+    }
+    if let Some(mode) = options.filter {
+        if let Err(e) = display.set_filter_mode(&mut cpu.gpu, mode) {
+            eprintln!("Error Setting Filter {}", e);
+        }
+    }
+    if let Some(mode) = options.scale_mode {
+        display.set_scale_mode(&mut cpu.gpu, mode);
+    }
+
+    let mut control = Control::new();
+    let wait = Duration::from_nanos(1_000_000_000 / recorder::FPS);
+
+    loop {
+        let start = Instant::now();
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } => return Next::Quit,
+
+                // a dropped ROM replaces the game, anything else is only
+                // reported
+                Event::DropFile { filename, .. } if dropped(osd, &filename) => {
+                    return Next::Load(filename)
+                }
+
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..),
                     ..
-                } => cpu.key[0x0] = 1,
-                Event::KeyDown {
-                    keycode: Some(Keycode::Num2),
+                }
+                | Event::Window {
+                    win_event: WindowEvent::Exposed,
                     ..
-                } => cpu.key[0x1] = 1,
+                } => cpu.gpu.draw_flag = true,
+
+                // Esc goes back to the launcher, F4 to the recent ROMs
                 Event::KeyDown {
-                    keycode: Some(Keycode::Num3),
+                    keycode: Some(Keycode::Escape),
                     ..
-                } => cpu.key[0x2] = 1,
+                } => return Next::Launcher(false),
                 Event::KeyDown {
-                    keycode: Some(Keycode::Num4),
+                    keycode: Some(Keycode::F4),
                     ..
-                } => cpu.key[0x3] = 1,
+                } => return Next::Launcher(true),
 
-                // qwer
+                // F1 breaks into the debugger on the terminal
                 Event::KeyDown {
-                    keycode: Some(Keycode::Q),
+                    keycode: Some(Keycode::F1),
                     ..
-                } => cpu.key[0x4] = 1,
+                } => debugger.pause(),
+
+                // F2 saves the quirks, speed and palette in the ROM's profile
                 Event::KeyDown {
-                    keycode: Some(Keycode::W),
-                    ..
-                } => cpu.key[0x5] = 1,
+                    keycode: Some(Keycode::F2),
+                    ..
+                } => {
+                    let profile = Profile {
+                        platform: options.platform,
+                        quirks: config::quirk_list(cpu.quirks()),
+                        speed: Some(cpu.speed()),
+                        palette: Some(display.palette().clone()),
+                        ..Profile::default()
+                    };
+                    let name = Path::new(rom).file_name().unwrap().to_string_lossy();
+                    match config::save_profile(options.config(), &name, data, &profile) {
+                        Ok(()) => {
+                            notify(osd, &format!("Saved the settings to {}", options.config()))
+                        }
+                        Err(e) => eprintln!("Error Writing {}: {}", options.config(), e),
+                    }
+                }
+
+                // F12 saves a screenshot to the current directory, Shift+F12
+                // the raw PBM at the native resolution
                 Event::KeyDown {
-                    keycode: Some(Keycode::E),
-                    ..
-                } => cpu.key[0x6] = 1,
+                    keycode: Some(Keycode::F12),
+                    keymod,
+                    ..
+                } => {
+                    let pbm = keymod.intersects(LSHIFTMOD | RSHIFTMOD);
+                    let path = output_name(rom, if pbm { "pbm" } else { "png" });
+                    match screenshot::save(&cpu.gpu, scale as usize, display.palette(), &path) {
+                        Ok(()) => notify(osd, &format!("Saved {}", path)),
+                        Err(e) => eprintln!("Error Writing {}: {}", path, e),
+                    }
+                }
+
+                // F6 toggles the CRT effects
                 Event::KeyDown {
-                    keycode: Some(Keycode::R),
-                    ..
-                } => cpu.key[0x7] = 1,
+                    keycode: Some(Keycode::F6),
+                    ..
+                } => {
+                    let mut crt = display.crt();
+                    crt.enabled = !crt.enabled;
+                    notify(
+                        osd,
+                        &format!("CRT {}", if crt.enabled { "on" } else { "off" }),
+                    );
+                    display.set_crt(&mut cpu.gpu, crt);
+                }
 
-                // asdf
+                // F7 cycles the anti-flicker filters
                 Event::KeyDown {
-                    keycode: Some(Keycode::A),
-                    ..
-                } => cpu.key[0x8] = 1,
+                    keycode: Some(Keycode::F7),
+                    ..
+                } => {
+                    let mode = display.filter_mode().next();
+                    notify(osd, &format!("Filter {:?}", mode));
+                    if let Err(e) = display.set_filter_mode(&mut cpu.gpu, mode) {
+                        eprintln!("Error Setting Filter {}", e);
+                    }
+                }
+
+                // F8 switches between fit, integer and stretch scaling
                 Event::KeyDown {
-                    keycode: Some(Keycode::S),
+                    keycode: Some(Keycode::F8),
                     ..
-                } => cpu.key[0x9] = 1,
+                } => {
+                    let mode = display.scale_mode().next();
+                    notify(osd, &format!("Scaling {:?}", mode));
+                    display.set_scale_mode(&mut cpu.gpu, mode);
+                }
+
+                // F9 cycles through the palettes
                 Event::KeyDown {
-                    keycode: Some(Keycode::D),
-                    ..
-                } => cpu.key[0xA] = 1,
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => {
+                    let current = palettes.iter().position(|p| p == display.palette());
+                    let next = current.map_or(0, |i| (i + 1) % palettes.len());
+                    notify(osd, &format!("Palette {}", palettes[next].name));
+                    display.set_palette(&mut cpu.gpu, palettes[next].clone());
+                }
+
+                // F10 toggles fullscreen
                 Event::KeyDown {
-                    keycode: Some(Keycode::F),
+                    keycode: Some(Keycode::F10),
                     ..
-                } => cpu.key[0xB] = 1,
+                } => display.toggle_fullscreen(&mut cpu.gpu),
 
-                //zxcv
+                // F11 starts and stops recording a GIF
                 Event::KeyDown {
-                    keycode: Some(Keycode::Z),
-                    ..
-                } => cpu.key[0xC] = 1,
+                    keycode: Some(Keycode::F11),
+                    ..
+                } => match recorder.take() {
+                    Some(mut r) => {
+                        if let Err(e) = r.finish() {
+                            eprintln!("Error Writing Recording {}", e);
+                        }
+                        notify(osd, "Recording stopped");
+                    }
+                    None => {
+                        let path = output_name(rom, "gif");
+                        let gif = File::create(&path)
+                            .and_then(|f| GifRecorder::new(f, scale as usize, display.palette()));
+                        match gif {
+                            Ok(gif) => {
+                                notify(osd, &format!("Recording to {}", path));
+                                *recorder = Some(Box::new(gif));
+                            }
+                            Err(e) => eprintln!("Error Writing {}: {}", path, e),
+                        }
+                    }
+                },
+
+                // F3 pauses and resumes, `.` runs a single frame
                 Event::KeyDown {
-                    keycode: Some(Keycode::X),
+                    keycode: Some(Keycode::F3),
                     ..
-                } => cpu.key[0xD] = 1,
+                } => {
+                    let paused = control.toggle_pause();
+                    notify(osd, if paused { "Paused" } else { "Resumed" });
+                }
                 Event::KeyDown {
-                    keycode: Some(Keycode::C),
+                    keycode: Some(Keycode::Period),
                     ..
-                } => cpu.key[0xE] = 1,
+                } => control.advance_frame(),
+
+                // F5 starts the game over, Shift+F5 as if the machine was
+                // switched off and on
                 Event::KeyDown {
-                    keycode: Some(Keycode::V),
-                    ..
-                } => cpu.key[0xF] = 1,
+                    keycode: Some(Keycode::F5),
+                    keymod,
+                    ..
+                } => {
+                    if keymod.intersects(LSHIFTMOD | RSHIFTMOD) {
+                        cpu.hard_reset();
+                        notify(osd, "Hard reset");
+                    } else {
+                        cpu.soft_reset();
+                        notify(osd, "Reset");
+                    }
+                    debugger.reset_history(cpu);
+                }
 
-                // ---- KEY UP ----
-                // 1234
-                Event::KeyUp {
-                    keycode: Some(Keycode::Num1),
-                    ..
-                } => cpu.key[0x0] = 0,
-                Event::KeyUp {
-                    keycode: Some(Keycode::Num2),
-                    ..
-                } => cpu.key[0x1] = 0,
-                Event::KeyUp {
-                    keycode: Some(Keycode::Num3),
+                // Tab fast forwards while held, Backspace toggles slow motion
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
                     ..
-                } => cpu.key[0x2] = 0,
+                } => control.set_fast_forward(true),
                 Event::KeyUp {
-                    keycode: Some(Keycode::Num4),
+                    keycode: Some(Keycode::Tab),
                     ..
-                } => cpu.key[0x3] = 0,
+                } => control.set_fast_forward(false),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => {
+                    let on = !control.slow_motion();
+                    control.set_slow_motion(on);
+                    notify(
+                        osd,
+                        &format!("Slow motion {}", if on { "on" } else { "off" }),
+                    );
+                }
 
-                // qwer
-                Event::KeyUp {
-                    keycode: Some(Keycode::Q),
+                // - and = change the instructions per frame
+                Event::KeyDown {
+                    keycode: Some(Keycode::Minus),
                     ..
-                } => cpu.key[0x4] = 0,
-                Event::KeyUp {
-                    keycode: Some(Keycode::W),
+                }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::KpMinus),
                     ..
-                } => cpu.key[0x5] = 0,
-                Event::KeyUp {
-                    keycode: Some(Keycode::E),
+                } => {
+                    notify(osd, &format!("Speed {}", control::slower(cpu)));
+                    debugger.reset_history(cpu);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Equals),
                     ..
-                } => cpu.key[0x6] = 0,
-                Event::KeyUp {
-                    keycode: Some(Keycode::R),
+                }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::KpPlus),
                     ..
-                } => cpu.key[0x7] = 0,
+                } => {
+                    notify(osd, &format!("Speed {}", control::faster(cpu)));
+                    debugger.reset_history(cpu);
+                }
 
-                // asdf
-                Event::KeyUp {
-                    keycode: Some(Keycode::A),
-                    ..
-                } => cpu.key[0x8] = 0,
-                Event::KeyUp {
-                    keycode: Some(Keycode::S),
-                    ..
-                } => cpu.key[0x9] = 0,
-                Event::KeyUp {
-                    keycode: Some(Keycode::D),
-                    ..
-                } => cpu.key[0xA] = 0,
-                Event::KeyUp {
-                    keycode: Some(Keycode::F),
+                // ` shows and hides the FPS, speed and ROM name
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backquote),
                     ..
-                } => cpu.key[0xB] = 0,
+                } => osd.toggle_stats(),
 
-                //zxcv
-                Event::KeyUp {
-                    keycode: Some(Keycode::Z),
-                    ..
-                } => cpu.key[0xC] = 0,
-                Event::KeyUp {
-                    keycode: Some(Keycode::X),
-                    ..
-                } => cpu.key[0xD] = 0,
-                Event::KeyUp {
-                    keycode: Some(Keycode::C),
-                    ..
-                } => cpu.key[0xE] = 0,
+                // the keypad buttons follow every key that is held
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } => {
+                    held.insert(key.name());
+                }
                 Event::KeyUp {
-                    keycode: Some(Keycode::V),
-                    ..
-                } => cpu.key[0xF] = 0,
+                    keycode: Some(key), ..
+                } => {
+                    held.remove(&key.name());
+                }
+
+                Event::ControllerDeviceAdded { .. }
+                | Event::ControllerDeviceRemoved { .. }
+                | Event::ControllerButtonDown { .. }
+                | Event::ControllerButtonUp { .. }
+                | Event::ControllerAxisMotion { .. } => {
+                    if let Some(ref mut pads) = *gamepads {
+                        pads.handle(&event);
+                    }
+                }
 
                 _ => {}
             }
         }
+        cpu.key = [0; 16];
+        let pad_held = gamepads.as_ref().map(|pads| pads.held());
+        for key in held.iter().chain(pad_held.into_iter().flatten()) {
+            if let Some(button) = keymap.button(key) {
+                cpu.key[button] = 1;
+            }
+        }
 
-        cpu.emulate_cycle();
-        cpu.gpu.render_screen();
+        // a frame's worth of instructions, or one debugger command while
+        // the debugger is paused so that the window keeps up with stepping.
+        // Fast forward runs frames for as long as one normally takes.
+        while debugger.paused() || control.run_frame() {
+            let frame = cpu.frames();
+            while cpu.frames() == frame {
+                if !debugger.run_cycle(cpu) {
+                    // a program that stopped goes back to the launcher
+                    return match cpu.error() {
+                        Some(e) => {
+                            eprintln!("error: {}: {}", rom, e);
+                            Next::Launcher(false)
+                        }
+                        None => Next::Quit,
+                    };
+                }
+                if debugger.paused() {
+                    break;
+                }
+            }
+
+            if cpu.frames() != frame && !capture(recorder, &cpu.gpu) {
+                return Next::Quit;
+            }
+            if options.frames.is_some_and(|n| cpu.frames() >= n) {
+                return Next::Quit;
+            }
+            if debugger.paused() || !control.fast_forward() || start.elapsed() >= wait {
+                break;
+            }
+        }
+        let status = if control.paused() {
+            "PAUSED"
+        } else if control.fast_forward() {
+            "FAST FORWARD"
+        } else if control.slow_motion() {
+            "SLOW MOTION"
+        } else {
+            ""
+        };
+        osd.set_status(status);
+        osd.update(cpu.frames(), cpu.cycles());
+        let frame = cpu.frames();
+        display.render_screen(&mut cpu.gpu, frame, osd);
 
         //to keep a constant fps
         let elapsed = start.elapsed();
-        if wait > elapsed {
-            thread::sleep(wait - elapsed);
+        let frame_time = control.frame_time();
+        if frame_time > elapsed {
+            thread::sleep(frame_time - elapsed);
         }
     }
 }
+
+// Shows a status message over the game, and on the terminal as before.
+fn notify(osd: &mut Osd, message: &str) {
+    eprintln!("{}", message);
+    osd.message(message);
+}
+
+// Whether a file dropped on the window is a ROM or an archive to load,
+// telling the user when it is not.
+fn dropped(osd: &mut Osd, filename: &str) -> bool {
+    if rom::is_rom_file(filename) {
+        return true;
+    }
+    let name = Path::new(filename)
+        .file_name()
+        .map_or(filename.into(), |n| n.to_string_lossy());
+    notify(osd, &format!("Not a ROM: {}", name));
+    false
+}
+
+// Gives the recorder a frame; false when it failed.
+fn capture(recorder: &mut Option<Box<dyn Recorder>>, gpu: &Gpu) -> bool {
+    if let Some(ref mut r) = *recorder {
+        if let Err(e) = r.capture(gpu) {
+            eprintln!("Error Writing Recording {}", e);
+            return false;
+        }
+    }
+    true
+}
+
+// `<rom>-<unix time>.<ext>` in the current directory
+fn output_name(rom: &str, ext: &str) -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let stem = Path::new(rom).file_stem().unwrap().to_string_lossy();
+    format!("{}-{}.{}", stem, secs, ext)
+}
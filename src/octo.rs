@@ -0,0 +1,249 @@
+use config::Profile;
+use gif;
+use palette::Palette;
+use serde_json::{self, Value};
+use std::fs;
+use std::path::Path;
+
+// An Octo cartridge: a GIF with an Octo program and its options hidden in
+// the pixels. Each pair of pixels holds a byte in the low four bits of
+// their colour indices, high half first, through every frame. The bytes are
+// a big-endian 32-bit length and that much JSON:
+//
+//     {"program": "<Octo source>", "options": {"tickrate": 20, ...}}
+pub struct Cartridge {
+    pub program: String,
+    pub options: Value,
+}
+
+// Whether `data` looks like a GIF, and so maybe a cartridge.
+pub fn is_gif(data: &[u8]) -> bool {
+    data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a")
+}
+
+pub fn read_cartridge(data: &[u8]) -> Result<Cartridge, String> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(data).map_err(|e| e.to_string())?;
+
+    let mut bytes = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().map_err(|e| e.to_string())? {
+        for pair in frame.buffer.chunks(2) {
+            if let [high, low] = *pair {
+                bytes.push((high & 0xF) << 4 | (low & 0xF));
+            }
+        }
+    }
+
+    if bytes.len() < 4 {
+        return Err("not an Octo cartridge".to_string());
+    }
+    let size = (bytes[0] as usize) << 24
+        | (bytes[1] as usize) << 16
+        | (bytes[2] as usize) << 8
+        | bytes[3] as usize;
+    let json = bytes
+        .get(4..4 + size)
+        .ok_or_else(|| "not an Octo cartridge".to_string())?;
+    let payload: Value =
+        serde_json::from_slice(json).map_err(|_| "not an Octo cartridge".to_string())?;
+
+    Ok(Cartridge {
+        program: payload["program"]
+            .as_str()
+            .ok_or_else(|| "the cartridge has no program".to_string())?
+            .to_string(),
+        options: payload["options"].clone(),
+    })
+}
+
+// Octo's options as settings, with a warning for every option that has no
+// equivalent here. Either the options themselves, or something with them
+// under "options" like a cartridge's payload, will do.
+pub fn profile(options: &Value) -> Result<(Profile, Vec<String>), String> {
+    let options = match options.get("options") {
+        Some(inner) => inner,
+        None => options,
+    };
+    let options = options
+        .as_object()
+        .ok_or("Octo options must be a JSON object")?;
+
+    let mut profile = Profile::default();
+    let mut warnings = Vec::new();
+    let boolean = |key: &str, value: &Value| {
+        value
+            .as_bool()
+            .ok_or_else(|| format!("{} must be true or false", key))
+    };
+    let string = |key: &str, value: &Value| {
+        value
+            .as_str()
+            .map(|s| s.to_string())
+            .ok_or_else(|| format!("{} must be a string", key))
+    };
+
+    for (key, value) in options {
+        match key.as_str() {
+            "shiftQuirks" => profile
+                .quirks
+                .push(("shift".to_string(), boolean(key, value)?)),
+            "loadStoreQuirks" => profile
+                .quirks
+                .push(("load-store".to_string(), boolean(key, value)?)),
+            "jumpQuirks" => profile
+                .quirks
+                .push(("jump".to_string(), boolean(key, value)?)),
+            "logicQuirks" => profile
+                .quirks
+                .push(("vf-reset".to_string(), boolean(key, value)?)),
+            "clipQuirks" => profile
+                .quirks
+                .push(("clip".to_string(), boolean(key, value)?)),
+            // Octo's ticks are instructions per frame too
+            "tickrate" => match value.as_u64() {
+                Some(rate) if rate > 1000 => {
+                    warnings.push(format!("tickrate {} is too fast, using 1000", rate));
+                    profile.speed = Some(1000);
+                }
+                Some(rate) if rate > 0 => profile.speed = Some(rate),
+                _ => return Err("tickrate must be a number above 0".to_string()),
+            },
+            // read together below
+            "backgroundColor" | "fillColor" | "fillColor2" | "blendColor" => {
+                string(key, value)?;
+            }
+            // 8xy_ always set VF before the result, which then wins if it
+            // goes in VF too
+            "vfOrderQuirks" => {
+                if !boolean(key, value)? {
+                    warnings.push(
+                        "vfOrderQuirks off is not supported, VF is always set before the result"
+                            .to_string(),
+                    );
+                }
+            }
+            "vBlankQuirks" => {
+                if boolean(key, value)? {
+                    warnings.push(
+                        "vBlankQuirks is not supported, sprites are drawn at once".to_string(),
+                    );
+                }
+            }
+            "screenRotation" => {
+                if value.as_u64() != Some(0) {
+                    warnings.push(format!("screenRotation {} is not supported", value));
+                }
+            }
+            "fontStyle" => {
+                let font = string(key, value)?;
+                if font != "octo" {
+                    warnings.push(format!(
+                        "fontStyle {} is not supported, using the octo font",
+                        font
+                    ));
+                }
+            }
+            "maxSize" => {
+                if value.as_u64().is_none_or(|size| size > 3584) {
+                    warnings.push(format!(
+                        "maxSize {} is not supported, ROMs can be 3584 bytes",
+                        value
+                    ));
+                }
+            }
+            "buzzColor" | "quietColor" => {
+                string(key, value)?;
+                warnings.push(format!(
+                    "{} is not supported, there is no border to colour",
+                    key
+                ));
+            }
+            "touchInputMode" => {
+                if string(key, value)? != "none" {
+                    warnings.push("touchInputMode is not supported".to_string());
+                }
+            }
+            _ => warnings.push(format!("unknown option {}", key)),
+        }
+    }
+
+    // two colours, or four for both planes
+    let colour = |key: &str| options.get(key).and_then(|c| c.as_str());
+    let colours = match (colour("backgroundColor"), colour("fillColor")) {
+        (Some(back), Some(fill)) => match (colour("fillColor2"), colour("blendColor")) {
+            (Some(fill2), Some(blend)) => vec![back, fill, fill2, blend],
+            (None, None) => vec![back, fill],
+            _ => {
+                warnings.push(
+                    "fillColor2 and blendColor go together, only the first two colours are used"
+                        .to_string(),
+                );
+                vec![back, fill]
+            }
+        },
+        (None, None) => vec![],
+        _ => return Err("backgroundColor and fillColor go together".to_string()),
+    };
+    if !colours.is_empty() {
+        profile.palette = Some(Palette::parse(&colours.join(","))?);
+    }
+
+    Ok((profile, warnings))
+}
+
+// Reads Octo options from JSON.
+pub fn read_options(json: &[u8]) -> Result<(Profile, Vec<String>), String> {
+    let options: Value = serde_json::from_slice(json).map_err(|e| e.to_string())?;
+    profile(&options)
+}
+
+// Reads Octo options from a JSON file, printing the warnings.
+pub fn load_options<P: AsRef<Path>>(path: P) -> Option<Profile> {
+    let path = path.as_ref();
+    match fs::read(path)
+        .map_err(|e| e.to_string())
+        .and_then(|j| read_options(&j))
+    {
+        Ok((profile, warnings)) => {
+            for warning in warnings {
+                eprintln!("{}: {}", path.display(), warning);
+            }
+            Some(profile)
+        }
+        Err(e) => {
+            eprintln!("Error Reading {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warnings(json: &str) -> Vec<String> {
+        read_options(json.as_bytes()).unwrap().1
+    }
+
+    #[test]
+    fn vf_order_warns_when_off() {
+        assert!(warnings(r#"{"vfOrderQuirks": true}"#).is_empty());
+        assert_eq!(warnings(r#"{"vfOrderQuirks": false}"#).len(), 1);
+    }
+
+    #[test]
+    fn half_of_the_extra_colours_warns() {
+        let two =
+            r##"{"backgroundColor": "#000000", "fillColor": "#FFFFFF", "blendColor": "#888888"}"##;
+        let (profile, warnings) = read_options(two.as_bytes()).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(profile.palette == Palette::parse("000000,ffffff").ok());
+
+        let four = r##"{"backgroundColor": "#000000", "fillColor": "#FFFFFF",
+            "fillColor2": "#FF0000", "blendColor": "#888888"}"##;
+        let (profile, warnings) = read_options(four.as_bytes()).unwrap();
+        assert!(warnings.is_empty());
+        assert!(profile.palette == Palette::parse("000000,ffffff,ff0000,888888").ok());
+    }
+}
@@ -0,0 +1,476 @@
+use config::{self, Config, Profile};
+use cpu::{Cpu, LoadError};
+use crt::CrtSettings;
+#[cfg(feature = "sdl")]
+use display::ScaleMode;
+use filter::FilterMode;
+use octo;
+use osd::Stats;
+use palette::Palette;
+use patch;
+use quirks::{Platform, Quirks};
+use rom::{self, Rom};
+use romdb::RomDb;
+use std::path::Path;
+use std::str::FromStr;
+
+pub static USAGE: &str = "Usage: chip8 [OPTIONS] [ROM]
+
+Runs ROM. Without one the window opens the launcher, and the terminal
+and --headless run ./games/pong2.c8.
+
+Options:
+  --platform <NAME>       quirks of modern (default), vip or schip
+  --quirk <NAME>=<on|off> change one quirk: shift, load-store, jump,
+                          vf-reset or clip; can be repeated
+  --load-address <ADDR>   where the ROM goes, like 0x600 for ETI-660 ROMs
+                          (default 0x200)
+  --patch <FILE>          apply an IPS or BPS patch to the ROM; can be
+                          repeated
+  --speed <N>             instructions per frame at 60 frames a second
+                          (default 5)
+  --seed <N>              seed the random numbers for repeatable runs
+  --scale <N>             window, screenshot and recording scale
+                          (default 10)
+  --palette <PALETTE>     palette name, or 2 or 4 hex colours
+  --scale-mode <MODE>     fit, integer or stretch
+  --filter <FILTER>       off, blend, phosphor or phosphor:<frames>
+  --crt <SETTINGS>        on, off or strengths like scanlines=0.5,bloom=0
+  --osd <STATS>           show fps, ips and rom over the game, or all
+  --title <on|off>        show the ROM name in the window title (default
+                          on)
+  --keymap <FILE>         keymap file (default keymap.cfg)
+  --config <FILE>         settings and per-ROM profiles (default
+                          chip8.toml)
+  --octo-options <FILE>   Octo options JSON for the quirks, speed and
+                          colours (default ROM.json next to the ROM)
+  --romdb <FILE>          more ROMs for the built-in ROM database
+  --headless              run without a window or input, as fast as
+                          possible
+  --frames <N>            stop after N frames
+  --debug                 start paused in the debugger
+  --coverage <PREFIX>     write the ROM coverage to PREFIX.cov, .json and
+                          .lst on exit
+  --trace <FILE>          write every instruction run to FILE
+  --raw-video             stream raw RGB24 frames to stdout
+  -h, --help              show this help
+";
+
+// Command line options. Settings that are not given are None, so that
+// they can come from somewhere else.
+#[derive(Clone, Default)]
+pub struct Options {
+    pub rom: Option<String>,
+    pub platform: Option<Platform>,
+    // `--quirk` overrides, in order
+    pub quirks: Vec<(String, bool)>,
+    pub load_address: Option<usize>,
+    // IPS and BPS patches, applied in order
+    pub patches: Vec<String>,
+    pub speed: Option<u64>,
+    pub seed: Option<u64>,
+    pub scale: Option<u32>,
+    pub palette: Option<Palette>,
+    #[cfg(feature = "sdl")]
+    pub scale_mode: Option<ScaleMode>,
+    pub filter: Option<FilterMode>,
+    pub crt: Option<CrtSettings>,
+    pub osd: Option<Stats>,
+    pub keymap: Option<String>,
+    pub config: Option<String>,
+    pub octo_options: Option<String>,
+    pub romdb: Option<String>,
+    // key mappings from the config file, applied over the keymap files
+    pub keys: Vec<(usize, Vec<String>)>,
+    pub sound: Option<bool>,
+    pub title: Option<bool>,
+    pub headless: bool,
+    pub frames: Option<u64>,
+    pub debug: bool,
+    pub coverage: Option<String>,
+    pub trace: Option<String>,
+    pub raw_video: bool,
+    pub help: bool,
+}
+
+impl Options {
+    // Parses the arguments after the program name. Errors are meant to be
+    // shown to the user as they are.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            // `--flag value` or `--flag=value`
+            let (flag, mut inline) = match arg.find('=') {
+                Some(p) if arg.starts_with("--") => {
+                    (arg[..p].to_string(), Some(arg[p + 1..].to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+
+            match flag.as_str() {
+                "--platform" => {
+                    let name = value(&flag, &mut inline, &mut args)?;
+                    options.platform = Some(Platform::parse(&name).ok_or_else(|| {
+                        format!("unknown platform `{}`, expected modern, vip or schip", name)
+                    })?);
+                }
+                "--quirk" => {
+                    let quirk = value(&flag, &mut inline, &mut args)?;
+                    options.quirks.push(Quirks::parse_override(&quirk)?);
+                }
+                "--load-address" => {
+                    let address = value(&flag, &mut inline, &mut args)?;
+                    options.load_address = Some(hex(&flag, &address)?);
+                }
+                "--patch" => options.patches.push(value(&flag, &mut inline, &mut args)?),
+                "--speed" => {
+                    let speed = value(&flag, &mut inline, &mut args)?;
+                    options.speed = Some(positive(&flag, &speed, 1000)?);
+                }
+                "--seed" => {
+                    let seed = value(&flag, &mut inline, &mut args)?;
+                    options.seed = Some(number(&flag, &seed)?);
+                }
+                "--scale" => {
+                    let scale = value(&flag, &mut inline, &mut args)?;
+                    options.scale = Some(positive(&flag, &scale, 100)? as u32);
+                }
+                "--palette" => {
+                    let palette = value(&flag, &mut inline, &mut args)?;
+                    options.palette = Some(Palette::parse(&palette)?);
+                }
+                #[cfg(feature = "sdl")]
+                "--scale-mode" => {
+                    let mode = value(&flag, &mut inline, &mut args)?;
+                    options.scale_mode = Some(ScaleMode::parse(&mode).ok_or_else(|| {
+                        format!(
+                            "unknown scale mode `{}`, expected fit, integer or stretch",
+                            mode
+                        )
+                    })?);
+                }
+                "--filter" => {
+                    let filter = value(&flag, &mut inline, &mut args)?;
+                    options.filter = Some(FilterMode::parse(&filter).ok_or_else(|| {
+                        format!(
+                            "unknown filter `{}`, expected off, blend, phosphor or phosphor:<frames>",
+                            filter
+                        )
+                    })?);
+                }
+                "--crt" => {
+                    let crt = value(&flag, &mut inline, &mut args)?;
+                    options.crt =
+                        Some(CrtSettings::parse(&crt).map_err(|e| format!("--crt: {}", e))?);
+                }
+                "--osd" => {
+                    let osd = value(&flag, &mut inline, &mut args)?;
+                    options.osd = Some(Stats::parse(&osd).map_err(|e| format!("--osd: {}", e))?);
+                }
+                "--title" => {
+                    let title = value(&flag, &mut inline, &mut args)?;
+                    options.title = Some(switch(&flag, &title)?);
+                }
+                "--keymap" => options.keymap = Some(value(&flag, &mut inline, &mut args)?),
+                "--config" => options.config = Some(value(&flag, &mut inline, &mut args)?),
+                "--octo-options" => {
+                    options.octo_options = Some(value(&flag, &mut inline, &mut args)?)
+                }
+                "--romdb" => options.romdb = Some(value(&flag, &mut inline, &mut args)?),
+                "--frames" => {
+                    let frames = value(&flag, &mut inline, &mut args)?;
+                    options.frames = Some(positive(&flag, &frames, u64::MAX)?);
+                }
+                "--coverage" => options.coverage = Some(value(&flag, &mut inline, &mut args)?),
+                "--trace" => options.trace = Some(value(&flag, &mut inline, &mut args)?),
+                "--headless" => options.headless = true,
+                "--debug" => options.debug = true,
+                "--raw-video" => options.raw_video = true,
+                "-h" | "--help" => options.help = true,
+                _ if flag.starts_with('-') => return Err(format!("unknown option `{}`", flag)),
+                _ => {
+                    if let Some(ref rom) = options.rom {
+                        return Err(format!("more than one ROM given: `{}` and `{}`", rom, arg));
+                    }
+                    options.rom = Some(arg.clone());
+                }
+            }
+
+            if inline.is_some() {
+                return Err(format!("{} does not take a value", flag));
+            }
+        }
+
+        Ok(options)
+    }
+
+    pub fn rom(&self) -> &str {
+        self.rom.as_ref().map_or("./games/pong2.c8", |r| r.as_str())
+    }
+
+    pub fn config(&self) -> &str {
+        self.config.as_ref().map_or("chip8.toml", |c| c.as_str())
+    }
+
+    // Reads the ROM, with the patches applied.
+    pub fn read_rom(&self) -> Result<Rom, LoadError> {
+        let mut rom = rom::read(self.rom())?;
+        for path in &self.patches {
+            rom.data = patch::apply_file(&rom.data, path).map_err(LoadError::Patch)?;
+        }
+        Ok(rom)
+    }
+
+    // Fills in what the command line left out from the config file's
+    // defaults and the profile of the ROM, if there is a config file.
+    pub fn load_config(&mut self, rom: &[u8]) {
+        let path = self.config().to_string();
+        if !Path::new(&path).exists() {
+            return;
+        }
+        match Config::load(&path).map_err(|e| e.to_string()) {
+            Ok(c) => match c.profile(&config::rom_hash(rom)) {
+                Ok(profile) => self.merge(profile),
+                Err(e) => eprintln!("Error Reading {}: {}", path, e),
+            },
+            Err(e) => eprintln!("Error Reading {}: {}", path, e),
+        }
+    }
+
+    // Takes what is still left out from the Octo options given with
+    // `--octo-options`, or else the ones that came with the ROM.
+    pub fn merge_octo_options(&mut self, rom: Option<Profile>) {
+        let profile = match self.octo_options {
+            Some(ref path) => octo::load_options(path),
+            None => rom,
+        };
+        if let Some(profile) = profile {
+            self.merge(profile);
+        }
+    }
+
+    // The built-in ROM database with the one given by `--romdb` on top.
+    pub fn romdb(&self) -> RomDb {
+        let mut db = RomDb::builtin();
+        if let Some(ref path) = self.romdb {
+            match RomDb::load(path) {
+                Ok(more) => db.extend(more),
+                Err(e) => eprintln!("Error Reading {}: {}", path, e),
+            }
+        }
+        db
+    }
+
+    // Where the launcher looks for ROMs: games/ and the folders in the
+    // config file.
+    pub fn folders(&self) -> Vec<String> {
+        let mut folders = vec!["games".to_string()];
+        let path = self.config();
+        if Path::new(path).exists() {
+            match Config::load(path)
+                .map_err(|e| e.to_string())
+                .and_then(|c| c.folders())
+            {
+                Ok(more) => folders.extend(more),
+                Err(e) => eprintln!("Error Reading {}: {}", path, e),
+            }
+        }
+        folders
+    }
+
+    // Takes what is still left out from `profile`. Everything given before
+    // wins over it, quirk overrides and keys included.
+    pub fn merge(&mut self, profile: Profile) {
+        // a platform on the command line starts over from its quirks
+        if self.platform.is_none() {
+            self.platform = profile.platform;
+            let quirks = self.quirks.split_off(0);
+            self.quirks = profile.quirks;
+            self.quirks.extend(quirks);
+        }
+        self.speed = self.speed.or(profile.speed);
+        self.palette = self.palette.take().or(profile.palette);
+        let keys = self.keys.split_off(0);
+        self.keys = profile.keys;
+        self.keys.extend(keys);
+        self.sound = self.sound.or(profile.sound);
+        self.title = self.title.or(profile.title);
+    }
+
+    // Loads the ROM `data` at the load address, and takes what is still
+    // left out from the ROM database before setting up the Cpu.
+    pub fn load_rom(&mut self, cpu: &mut Cpu, data: &[u8]) -> Result<(), LoadError> {
+        if let Some(address) = self.load_address {
+            cpu.set_load_address(address)?;
+        }
+        cpu.load_bytes_with(data, &self.romdb())?;
+        if let Some(info) = cpu.rom_info.clone() {
+            self.merge(info.profile);
+        }
+        self.apply(cpu);
+        Ok(())
+    }
+
+    // The platform's quirks with the overrides applied.
+    pub fn quirks(&self) -> Quirks {
+        let mut quirks = self.platform.unwrap_or(Platform::Modern).quirks();
+        for &(ref name, on) in &self.quirks {
+            quirks.set(name, on).ok();
+        }
+        quirks
+    }
+
+    // Sets up the quirks, speed and seed.
+    pub fn apply(&self, cpu: &mut Cpu) {
+        cpu.set_quirks(self.quirks());
+        if let Some(speed) = self.speed {
+            cpu.set_speed(speed);
+        }
+        if let Some(seed) = self.seed {
+            cpu.set_seed(seed);
+        }
+    }
+}
+
+fn value<I: Iterator<Item = String>>(
+    flag: &str,
+    inline: &mut Option<String>,
+    args: &mut I,
+) -> Result<String, String> {
+    inline
+        .take()
+        .or_else(|| args.next())
+        .ok_or_else(|| format!("{} needs a value", flag))
+}
+
+fn number<T: FromStr>(flag: &str, s: &str) -> Result<T, String> {
+    s.parse()
+        .map_err(|_| format!("{} expects a number, got `{}`", flag, s))
+}
+
+// A number, in hex with `0x` in front.
+fn hex(flag: &str, s: &str) -> Result<usize, String> {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        usize::from_str_radix(&s[2..], 16)
+            .map_err(|_| format!("{} expects a number, got `{}`", flag, s))
+    } else {
+        number(flag, s)
+    }
+}
+
+// `on` or `off`.
+fn switch(flag: &str, s: &str) -> Result<bool, String> {
+    match s {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("{} expects on or off, got `{}`", flag, s)),
+    }
+}
+
+// A number from 1 to `max`.
+fn positive(flag: &str, s: &str, max: u64) -> Result<u64, String> {
+    match number(flag, s)? {
+        0 => Err(format!("{} must be at least 1", flag)),
+        n if n > max => Err(format!("{} must be at most {}", flag, max)),
+        n => Ok(n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn values_inline_or_after() {
+        let options = parse(&[
+            "--speed=12",
+            "--platform",
+            "vip",
+            "--quirk=shift=on",
+            "pong.c8",
+        ])
+        .unwrap();
+        assert_eq!(options.speed, Some(12));
+        assert_eq!(options.platform, Some(Platform::Vip));
+        assert_eq!(options.quirks, vec![("shift".to_string(), true)]);
+        assert_eq!(options.rom(), "pong.c8");
+
+        let options = parse(&["--load-address=0x600", "--seed", "42"]).unwrap();
+        assert_eq!(options.load_address, Some(0x600));
+        assert_eq!(options.seed, Some(42));
+        assert_eq!(options.rom(), "./games/pong2.c8");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse(&["--speed"]).err().unwrap(), "--speed needs a value");
+        assert_eq!(
+            parse(&["--speed", "fast"]).err().unwrap(),
+            "--speed expects a number, got `fast`"
+        );
+        assert_eq!(
+            parse(&["--speed=0"]).err().unwrap(),
+            "--speed must be at least 1"
+        );
+        assert_eq!(
+            parse(&["--bogus"]).err().unwrap(),
+            "unknown option `--bogus`"
+        );
+        assert_eq!(
+            parse(&["--debug=yes"]).err().unwrap(),
+            "--debug does not take a value"
+        );
+        assert_eq!(
+            parse(&["a.c8", "b.c8"]).err().unwrap(),
+            "more than one ROM given: `a.c8` and `b.c8`"
+        );
+        assert!(parse(&["--platform", "nes"]).is_err());
+        assert!(parse(&["--title", "maybe"]).is_err());
+    }
+
+    #[test]
+    fn command_line_wins() {
+        let mut options = parse(&["--speed", "20", "--quirk", "clip=off"]).unwrap();
+        options.merge(Profile {
+            platform: Some(Platform::Schip),
+            quirks: vec![("clip".to_string(), true), ("jump".to_string(), false)],
+            speed: Some(7),
+            sound: Some(false),
+            ..Profile::default()
+        });
+        assert_eq!(options.speed, Some(20));
+        assert_eq!(options.sound, Some(false));
+        assert_eq!(options.platform, Some(Platform::Schip));
+        // the profile's overrides go first, so the command line's win
+        let quirks = options.quirks();
+        assert!(!quirks.clip);
+        assert!(!quirks.jump);
+
+        // what is merged first wins over what comes later
+        options.merge(Profile {
+            speed: Some(3),
+            sound: Some(true),
+            ..Profile::default()
+        });
+        assert_eq!(options.sound, Some(false));
+    }
+
+    #[test]
+    fn command_line_platform_ignores_profile_quirks() {
+        let mut options = parse(&["--platform", "vip"]).unwrap();
+        options.merge(Profile {
+            platform: Some(Platform::Schip),
+            quirks: vec![("shift".to_string(), true)],
+            ..Profile::default()
+        });
+        assert_eq!(options.platform, Some(Platform::Vip));
+        assert!(options.quirks.is_empty());
+        assert!(options.quirks() == Platform::Vip.quirks());
+    }
+}
@@ -0,0 +1,301 @@
+use font::{self, CELL_HEIGHT, CELL_WIDTH};
+use std::time::{Duration, Instant};
+
+// The OSD is drawn at four times the CHIP-8 resolution so that text fits,
+// into its own image that goes over the screen.
+pub static WIDTH: usize = 256;
+pub static HEIGHT: usize = 128;
+
+// How long a message stays up.
+pub static MESSAGE_TIME: Duration = Duration::from_secs(2);
+// The most messages shown at once, the newest at the bottom.
+static MESSAGES: usize = 4;
+
+// White text on a translucent black backdrop, whatever the palette.
+static TEXT: [u8; 3] = [255, 255, 255];
+static BACKDROP: u8 = 160;
+
+// The figures the OSD can keep on the screen.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Stats {
+    // emulated frames a second
+    pub fps: bool,
+    // instructions a second
+    pub ips: bool,
+    // the title or file name of the game
+    pub rom: bool,
+}
+
+impl Stats {
+    // A list like `fps,ips,rom`, or `all` or `none`.
+    pub fn parse(s: &str) -> Result<Stats, String> {
+        let mut stats = Stats::default();
+        for name in s.split(',').map(|n| n.trim()) {
+            match name {
+                "fps" => stats.fps = true,
+                "ips" => stats.ips = true,
+                "rom" => stats.rom = true,
+                "all" => stats = Stats::all(),
+                "none" => {}
+                _ => {
+                    return Err(format!(
+                        "unknown OSD stat `{}`, expected fps, ips, rom, all or none",
+                        name
+                    ))
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    pub fn all() -> Stats {
+        Stats {
+            fps: true,
+            ips: true,
+            rom: true,
+        }
+    }
+
+    pub fn any(&self) -> bool {
+        self.fps || self.ips || self.rom
+    }
+}
+
+// Status messages that fade after a while, a state like "PAUSED" that stays
+// while it holds, and the stats, drawn over the game.
+pub struct Osd {
+    stats: Stats,
+    // what toggle_stats brings back
+    hidden: Stats,
+    rom: String,
+    status: String,
+    messages: Vec<(String, Instant)>,
+    // frames and instructions are counted over a second at a time
+    sample_start: Instant,
+    sample_frames: u64,
+    sample_cycles: u64,
+    fps: u64,
+    ips: u64,
+}
+
+impl Osd {
+    pub fn new(stats: Stats) -> Osd {
+        Osd {
+            stats,
+            hidden: Stats::default(),
+            rom: String::new(),
+            status: String::new(),
+            messages: Vec::new(),
+            sample_start: Instant::now(),
+            sample_frames: 0,
+            sample_cycles: 0,
+            fps: 0,
+            ips: 0,
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    pub fn set_stats(&mut self, stats: Stats) {
+        self.stats = stats;
+    }
+
+    // Hides the stats, or shows the ones last hidden, or else all of them.
+    pub fn toggle_stats(&mut self) {
+        if self.stats.any() {
+            self.hidden = self.stats;
+            self.stats = Stats::default();
+        } else if self.hidden.any() {
+            self.stats = self.hidden;
+        } else {
+            self.stats = Stats::all();
+        }
+    }
+
+    pub fn set_rom(&mut self, rom: &str) {
+        self.rom = rom.to_string();
+    }
+
+    // Shown in the top right corner until it changes; empty for none.
+    pub fn set_status(&mut self, status: &str) {
+        if self.status != status {
+            self.status = status.to_string();
+        }
+    }
+
+    // Shows `text` for MESSAGE_TIME.
+    pub fn message(&mut self, text: &str) {
+        self.messages.push((text.to_string(), Instant::now()));
+        if self.messages.len() > MESSAGES {
+            self.messages.remove(0);
+        }
+    }
+
+    // Takes the Cpu's frame and cycle counts, as often as the screen is
+    // drawn, to measure the speed.
+    pub fn update(&mut self, frames: u64, cycles: u64) {
+        // a new game starts the counts over
+        if frames < self.sample_frames || cycles < self.sample_cycles {
+            self.sample_frames = frames;
+            self.sample_cycles = cycles;
+            self.sample_start = Instant::now();
+            return;
+        }
+        let elapsed = self.sample_start.elapsed();
+        if elapsed < Duration::from_secs(1) {
+            return;
+        }
+        let seconds = elapsed.as_secs_f64();
+        self.fps = ((frames - self.sample_frames) as f64 / seconds).round() as u64;
+        self.ips = ((cycles - self.sample_cycles) as f64 / seconds).round() as u64;
+        self.sample_frames = frames;
+        self.sample_cycles = cycles;
+        self.sample_start = Instant::now();
+    }
+
+    // Whether there is anything to draw.
+    pub fn visible(&self) -> bool {
+        self.stats.any()
+            || !self.status.is_empty()
+            || self.messages.iter().any(|m| m.1.elapsed() < MESSAGE_TIME)
+    }
+
+    // Draws into an RGBA image WIDTH by HEIGHT that is transparent apart
+    // from the text, and returns whether anything was drawn.
+    pub fn render(&mut self, rgba: &mut Vec<u8>) -> bool {
+        self.messages.retain(|m| m.1.elapsed() < MESSAGE_TIME);
+        rgba.clear();
+        rgba.resize(WIDTH * HEIGHT * 4, 0);
+        if !self.visible() {
+            return false;
+        }
+
+        // the stats down the left, the status on the right
+        let mut stats = Vec::new();
+        if self.stats.rom && !self.rom.is_empty() {
+            stats.push(self.rom.clone());
+        }
+        if self.stats.fps {
+            stats.push(format!("{} FPS", self.fps));
+        }
+        if self.stats.ips {
+            stats.push(format!("{} IPS", self.ips));
+        }
+        for (row, line) in stats.iter().enumerate() {
+            text(rgba, 1, 1 + row * (CELL_HEIGHT + 1), line);
+        }
+        if !self.status.is_empty() {
+            let x = WIDTH
+                .saturating_sub(self.status.chars().count() * CELL_WIDTH)
+                .max(1);
+            text(rgba, x, 1, &self.status);
+        }
+
+        let bottom = HEIGHT - CELL_HEIGHT;
+        for (row, m) in self.messages.iter().rev().enumerate() {
+            text(rgba, 1, bottom - row * (CELL_HEIGHT + 1), &m.0);
+        }
+        true
+    }
+}
+
+// A line of text at (x, y) on its backdrop.
+fn text(rgba: &mut [u8], x: usize, y: usize, s: &str) {
+    let width = (s.chars().count() * CELL_WIDTH + 1).min(WIDTH + 1 - x);
+    for py in y - 1..(y + CELL_HEIGHT).min(HEIGHT) {
+        for px in x - 1..x - 1 + width {
+            let p = (py * WIDTH + px) * 4;
+            rgba[p..p + 4].copy_from_slice(&[0, 0, 0, BACKDROP]);
+        }
+    }
+    font::draw_text(rgba, WIDTH, x, y, s, TEXT);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A message that went up `ago` before now.
+    fn message_from(osd: &mut Osd, text: &str, ago: Duration) {
+        let shown = Instant::now().checked_sub(ago).unwrap();
+        osd.messages.push((text.to_string(), shown));
+    }
+
+    #[test]
+    fn messages_expire() {
+        let mut osd = Osd::new(Stats::default());
+        let mut rgba = Vec::new();
+        assert!(!osd.visible());
+        assert!(!osd.render(&mut rgba));
+        assert_eq!(rgba.len(), WIDTH * HEIGHT * 4);
+        assert!(rgba.iter().all(|&b| b == 0));
+
+        message_from(&mut osd, "OLD", MESSAGE_TIME);
+        assert!(!osd.visible());
+        message_from(&mut osd, "NEW", MESSAGE_TIME / 2);
+        assert!(osd.visible());
+        assert!(osd.render(&mut rgba));
+        assert_eq!(osd.messages.len(), 1);
+        assert!(rgba.iter().any(|&b| b != 0));
+    }
+
+    #[test]
+    fn newest_messages_kept() {
+        let mut osd = Osd::new(Stats::default());
+        for i in 0..MESSAGES + 2 {
+            osd.message(&i.to_string());
+        }
+        let texts: Vec<&str> = osd.messages.iter().map(|m| m.0.as_str()).collect();
+        assert_eq!(texts, vec!["2", "3", "4", "5"]);
+    }
+
+    #[test]
+    fn status_stays() {
+        let mut osd = Osd::new(Stats::default());
+        osd.set_status("PAUSED");
+        assert!(osd.visible());
+        osd.set_status("");
+        assert!(!osd.visible());
+    }
+
+    #[test]
+    fn stats() {
+        assert_eq!(
+            Stats::parse("fps, rom").unwrap(),
+            Stats {
+                fps: true,
+                ips: false,
+                rom: true
+            }
+        );
+        assert_eq!(Stats::parse("all").unwrap(), Stats::all());
+        assert!(!Stats::parse("none").unwrap().any());
+        assert!(Stats::parse("fps,frames").is_err());
+
+        let mut osd = Osd::new(Stats::parse("ips").unwrap());
+        osd.toggle_stats();
+        assert!(!osd.stats().any());
+        osd.toggle_stats();
+        assert_eq!(osd.stats(), Stats::parse("ips").unwrap());
+        // with nothing hidden yet, everything
+        let mut osd = Osd::new(Stats::default());
+        osd.toggle_stats();
+        assert_eq!(osd.stats(), Stats::all());
+    }
+
+    #[test]
+    fn speed_over_a_second() {
+        let mut osd = Osd::new(Stats::all());
+        osd.update(0, 0);
+        osd.sample_start = Instant::now().checked_sub(Duration::from_secs(2)).unwrap();
+        osd.update(120, 600);
+        assert_eq!((osd.fps, osd.ips), (60, 300));
+
+        // a new game starts the counts over
+        osd.update(10, 50);
+        assert_eq!(osd.sample_frames, 10);
+        assert_eq!((osd.fps, osd.ips), (60, 300));
+    }
+}
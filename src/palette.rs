@@ -0,0 +1,170 @@
+use std::fmt;
+
+// Colours indexed by pixel value: 0 is the background and 1 the lit colour.
+// Values 2 and 3 are for the second plane and both planes of XO-CHIP style
+// output; two-colour palettes use the lit colour for them. The Gpu only has
+// one plane, so for now they are kept but never shown.
+#[derive(Clone, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    pub colours: [[u8; 3]; 4],
+}
+
+static BUILTIN: [(&str, [[u8; 3]; 4]); 5] = [
+    (
+        "default",
+        [[255, 255, 255], [0, 0, 0], [85, 85, 85], [170, 170, 170]],
+    ),
+    (
+        "green",
+        [[8, 24, 8], [51, 255, 102], [26, 128, 51], [153, 255, 178]],
+    ),
+    (
+        "amber",
+        [[20, 12, 0], [255, 176, 0], [128, 88, 0], [255, 216, 128]],
+    ),
+    (
+        "lcd",
+        [[155, 188, 15], [15, 56, 15], [48, 98, 48], [139, 172, 15]],
+    ),
+    (
+        "inverted",
+        [[0, 0, 0], [255, 255, 255], [170, 170, 170], [85, 85, 85]],
+    ),
+];
+
+impl Palette {
+    pub fn builtin() -> Vec<Palette> {
+        BUILTIN
+            .iter()
+            .map(|&(name, colours)| Palette {
+                name: name.to_string(),
+                colours,
+            })
+            .collect()
+    }
+
+    // A built-in name, or two or four comma separated hex colours such as
+    // `#000000,#33ff66`.
+    pub fn parse(s: &str) -> Result<Palette, String> {
+        if let Some(p) = Palette::builtin().into_iter().find(|p| p.name == s) {
+            return Ok(p);
+        }
+
+        let colours: Vec<[u8; 3]> = s
+            .split(',')
+            .map(|c| parse_colour(c.trim()).ok_or(format!("bad colour `{}`", c.trim())))
+            .collect::<Result<_, _>>()?;
+
+        match colours.len() {
+            2 => Ok(Palette {
+                name: "custom".to_string(),
+                colours: [colours[0], colours[1], colours[1], colours[1]],
+            }),
+            4 => Ok(Palette {
+                name: "custom".to_string(),
+                colours: [colours[0], colours[1], colours[2], colours[3]],
+            }),
+            _ => Err(format!(
+                "unknown palette `{}`, expected a name or 2 or 4 colours",
+                s
+            )),
+        }
+    }
+
+    pub fn colour(&self, pixel: u8) -> [u8; 3] {
+        self.colours[pixel as usize & 3]
+    }
+
+    pub fn background(&self) -> [u8; 3] {
+        self.colours[0]
+    }
+
+    // Converts framebuffer pixels to RGBA, four bytes per pixel.
+    pub fn to_rgba(&self, pixels: &[u8], rgba: &mut Vec<u8>) {
+        rgba.clear();
+        for &p in pixels {
+            rgba.extend_from_slice(&self.colour(p));
+            rgba.push(255);
+        }
+    }
+}
+
+// The form `parse` reads: the name of a built-in palette, or the colours.
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if Palette::builtin().contains(self) {
+            return write!(f, "{}", self.name);
+        }
+        let colours: Vec<String> = self
+            .colours
+            .iter()
+            .map(|c| format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2]))
+            .collect();
+        write!(f, "{}", colours.join(","))
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::builtin().remove(0)
+    }
+}
+
+// `#rrggbb` or `rrggbb`
+pub fn parse_colour(s: &str) -> Option<[u8; 3]> {
+    let s = s.trim_start_matches('#');
+    if s.len() != 6 || !s.is_ascii() {
+        return None;
+    }
+
+    let byte = |i: usize| u8::from_str_radix(&s[i..i + 2], 16).ok();
+    Some([byte(0)?, byte(2)?, byte(4)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_colours() {
+        let p = Palette::parse("#000000, 33ff66").unwrap();
+        assert_eq!(
+            p.colours,
+            [[0, 0, 0], [51, 255, 102], [51, 255, 102], [51, 255, 102]]
+        );
+        assert_eq!(p.to_string(), "#000000,#33ff66,#33ff66,#33ff66");
+    }
+
+    #[test]
+    fn four_colours() {
+        let p = Palette::parse("#000000,#ffffff,#ff0000,#00ff00").unwrap();
+        assert_eq!(p.colour(2), [255, 0, 0]);
+        assert_eq!(p.colour(3), [0, 255, 0]);
+        assert!(Palette::parse(&p.to_string()).unwrap() == p);
+    }
+
+    #[test]
+    fn names() {
+        let green = Palette::parse("green").unwrap();
+        assert_eq!(green.name, "green");
+        assert_eq!(green.to_string(), "green");
+        assert!(Palette::parse("purple").is_err());
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(Palette::parse("#00000,#ffffff").is_err());
+        assert!(Palette::parse("#0000000,#ffffff").is_err());
+        assert!(Palette::parse("#gg0000,#ffffff").is_err());
+        assert!(Palette::parse("#000000,").is_err());
+        assert_eq!(parse_colour("é0000"), None);
+    }
+
+    #[test]
+    fn colour_count() {
+        assert!(Palette::parse("#000000").is_err());
+        assert!(Palette::parse("#000000,#111111,#222222").is_err());
+        assert!(Palette::parse("#000000,#111111,#222222,#333333,#444444").is_err());
+    }
+}
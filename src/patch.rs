@@ -0,0 +1,370 @@
+use cpu::MEMORY_SIZE;
+use crc32fast;
+use std::fs;
+use std::path::Path;
+
+// Applies an IPS or BPS patch to a ROM, telling them apart by their first
+// bytes. BPS patches carry checksums of the ROM they were made for, of the
+// patched ROM and of themselves, and any of them not matching is an error.
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        Err("not an IPS or BPS patch".to_string())
+    }
+}
+
+pub fn apply_file<P: AsRef<Path>>(rom: &[u8], path: P) -> Result<Vec<u8>, String> {
+    let path = path.as_ref();
+    let patch = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    apply(rom, &patch).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Records of a 3 byte offset, a 2 byte size and that many bytes to write
+// there, or a size of 0 and a 2 byte count of one byte to repeat. An offset
+// of "EOF" ends them, optionally followed by the 3 byte size to cut the
+// result to.
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = rom.to_vec();
+    let mut p = Reader {
+        data: patch,
+        pos: 5,
+    };
+
+    loop {
+        let offset = p.bytes(3)?;
+        if offset == b"EOF" {
+            break;
+        }
+        let offset = be(offset);
+        let size = be(p.bytes(2)?);
+        let (data, size) = if size == 0 {
+            let count = be(p.bytes(2)?);
+            (None, count)
+        } else {
+            (Some(p.bytes(size)?), size)
+        };
+
+        if out.len() < offset + size {
+            if offset + size > MEMORY_SIZE {
+                return Err(format!(
+                    "the IPS patch writes past the {} bytes of memory",
+                    MEMORY_SIZE
+                ));
+            }
+            out.resize(offset + size, 0);
+        }
+        match data {
+            Some(data) => out[offset..offset + size].copy_from_slice(data),
+            None => {
+                let value = p.byte()?;
+                for b in &mut out[offset..offset + size] {
+                    *b = value;
+                }
+            }
+        }
+    }
+
+    if let Ok(truncate) = p.bytes(3) {
+        out.truncate(be(truncate));
+    }
+    Ok(out)
+}
+
+// The source and target sizes and some metadata, then actions that build
+// the patched ROM from the ROM, the patch and what has been built so far.
+// The last 12 bytes are the CRC32s of the ROM, the patched ROM and the rest
+// of the patch.
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.len() < 16 {
+        return Err("the BPS patch is cut short".to_string());
+    }
+    let footer = patch.len() - 12;
+    let crc = |i: usize| le32(&patch[footer + i * 4..footer + i * 4 + 4]);
+    let (source_crc, target_crc, patch_crc) = (crc(0), crc(1), crc(2));
+
+    let actual = crc32fast::hash(&patch[..footer + 8]);
+    if actual != patch_crc {
+        return Err(format!(
+            "the BPS patch is damaged, its checksum is {:08x} instead of {:08x}",
+            actual, patch_crc
+        ));
+    }
+    let actual = crc32fast::hash(rom);
+    if actual != source_crc {
+        return Err(format!(
+            "the BPS patch is for another ROM, the ROM's checksum is {:08x} instead of {:08x}",
+            actual, source_crc
+        ));
+    }
+
+    let mut p = Reader {
+        data: &patch[..footer],
+        pos: 4,
+    };
+    let source_size = p.number()?;
+    let target_size = p.number()?;
+    let metadata = p.number()?;
+    p.bytes(metadata)?;
+    if source_size != rom.len() {
+        return Err(format!(
+            "the BPS patch is for a {} byte ROM, not {} bytes",
+            source_size,
+            rom.len()
+        ));
+    }
+    if target_size > MEMORY_SIZE {
+        return Err(format!(
+            "the BPS patch makes a {} byte ROM, more than the {} bytes of memory",
+            target_size, MEMORY_SIZE
+        ));
+    }
+
+    let mut out = Vec::with_capacity(target_size);
+    let (mut source_offset, mut target_offset) = (0usize, 0usize);
+    while p.pos < footer {
+        let action = p.number()?;
+        let length = (action >> 2) + 1;
+        if out.len() + length > target_size {
+            return Err("the BPS patch writes past the end of the ROM".to_string());
+        }
+        match action & 3 {
+            // source read: the ROM's bytes at the same place
+            0 => {
+                let at = out.len();
+                let bytes = rom
+                    .get(at..at + length)
+                    .ok_or("the BPS patch reads past the end of the ROM")?;
+                out.extend_from_slice(bytes);
+            }
+            // target read: bytes from the patch
+            1 => {
+                let bytes = p.bytes(length)?;
+                out.extend_from_slice(bytes);
+            }
+            // source copy: the ROM's bytes from somewhere else
+            2 => {
+                source_offset = relative(source_offset, p.number()?)?;
+                let bytes = rom
+                    .get(source_offset..source_offset + length)
+                    .ok_or("the BPS patch reads past the end of the ROM")?;
+                out.extend_from_slice(bytes);
+                source_offset += length;
+            }
+            // target copy: what was built so far, which can overlap what
+            // is being built
+            _ => {
+                target_offset = relative(target_offset, p.number()?)?;
+                for _ in 0..length {
+                    let b = *out
+                        .get(target_offset)
+                        .ok_or("the BPS patch copies what is not built yet")?;
+                    out.push(b);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if out.len() != target_size {
+        return Err(format!(
+            "the BPS patch made {} bytes instead of {}",
+            out.len(),
+            target_size
+        ));
+    }
+    let actual = crc32fast::hash(&out);
+    if actual != target_crc {
+        return Err(format!(
+            "the patched ROM's checksum is {:08x} instead of {:08x}",
+            actual, target_crc
+        ));
+    }
+    Ok(out)
+}
+
+// Moves an offset by a BPS relative number: the distance shifted up one,
+// with the bottom bit set for backwards.
+fn relative(offset: usize, n: usize) -> Result<usize, String> {
+    let distance = n >> 1;
+    if n & 1 == 0 {
+        Ok(offset + distance)
+    } else {
+        offset
+            .checked_sub(distance)
+            .ok_or_else(|| "the BPS patch copies from before the start".to_string())
+    }
+}
+
+fn be(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |n, &b| n << 8 | b as usize)
+}
+
+fn le32(bytes: &[u8]) -> u32 {
+    bytes.iter().rev().fold(0, |n, &b| n << 8 | b as u32)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + n)
+            .ok_or("the patch is cut short")?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    // BPS numbers: 7 bits a byte, lowest first, with the top bit set on the
+    // last byte and one added per byte to keep encodings unique
+    fn number(&mut self) -> Result<usize, String> {
+        let mut n = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let b = self.byte()?;
+            n = (b as usize & 0x7F)
+                .checked_mul(shift)
+                .and_then(|x| n.checked_add(x))
+                .ok_or("the BPS patch has a number too large")?;
+            if b & 0x80 != 0 {
+                return Ok(n);
+            }
+            shift = shift
+                .checked_shl(7)
+                .filter(|&s| s < 1 << 56)
+                .ok_or("the BPS patch has a number too large")?;
+            n += shift;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ips(records: &[u8]) -> Vec<u8> {
+        [b"PATCH", records].concat()
+    }
+
+    fn number(mut n: usize, out: &mut Vec<u8>) {
+        loop {
+            let bits = (n & 0x7F) as u8;
+            n >>= 7;
+            if n == 0 {
+                out.push(bits | 0x80);
+                return;
+            }
+            out.push(bits);
+            n -= 1;
+        }
+    }
+
+    // A BPS patch from `source` to a `target_size` byte ROM with the
+    // checksum `target_crc`, made by `actions`.
+    fn bps(source: &[u8], target_size: usize, target_crc: u32, actions: &[u8]) -> Vec<u8> {
+        let mut patch = b"BPS1".to_vec();
+        number(source.len(), &mut patch);
+        number(target_size, &mut patch);
+        number(0, &mut patch);
+        patch.extend_from_slice(actions);
+        patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        patch.extend_from_slice(&target_crc.to_le_bytes());
+        let crc = crc32fast::hash(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    static SOURCE: [u8; 4] = [1, 2, 3, 4];
+    static TARGET: [u8; 8] = [1, 2, 9, 9, 3, 4, 9, 9];
+
+    // one of each action: the first two bytes as they are, two new ones,
+    // the last two of the ROM and the two new ones again
+    fn actions() -> Vec<u8> {
+        let mut actions = Vec::new();
+        number(1 << 2, &mut actions);
+        number(1 << 2 | 1, &mut actions);
+        actions.extend_from_slice(&[9, 9]);
+        number(1 << 2 | 2, &mut actions);
+        number(2 << 1, &mut actions);
+        number(1 << 2 | 3, &mut actions);
+        number(2 << 1, &mut actions);
+        actions
+    }
+
+    #[test]
+    fn ips_record() {
+        let patch = ips(b"\x00\x00\x01\x00\x02\xAA\xBBEOF");
+        assert_eq!(apply(&[0; 4], &patch).unwrap(), [0, 0xAA, 0xBB, 0]);
+    }
+
+    #[test]
+    fn ips_rle_record() {
+        let patch = ips(b"\x00\x00\x02\x00\x00\x00\x03\x55EOF");
+        assert_eq!(apply(&[0; 4], &patch).unwrap(), [0, 0, 0x55, 0x55, 0x55]);
+    }
+
+    #[test]
+    fn ips_truncation() {
+        let patch = ips(b"EOF\x00\x00\x02");
+        assert_eq!(apply(&SOURCE, &patch).unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn ips_cut_short() {
+        let patch = ips(b"\x00\x00\x01\x00\x02\xAA");
+        assert!(apply(&SOURCE, &patch).is_err());
+    }
+
+    #[test]
+    fn ips_past_memory() {
+        let patch = ips(b"\x00\x0F\xFF\x00\x02\xAA\xBBEOF");
+        let e = apply(&SOURCE, &patch).unwrap_err();
+        assert!(e.contains("past the 4096 bytes"), "{}", e);
+    }
+
+    #[test]
+    fn bps_all_actions() {
+        let patch = bps(&SOURCE, TARGET.len(), crc32fast::hash(&TARGET), &actions());
+        assert_eq!(apply(&SOURCE, &patch).unwrap(), TARGET);
+    }
+
+    #[test]
+    fn bps_source_crc() {
+        let patch = bps(&SOURCE, TARGET.len(), crc32fast::hash(&TARGET), &actions());
+        let e = apply(&[1, 2, 3, 5], &patch).unwrap_err();
+        assert!(e.contains("for another ROM"), "{}", e);
+    }
+
+    #[test]
+    fn bps_target_crc() {
+        let patch = bps(&SOURCE, TARGET.len(), crc32fast::hash(&SOURCE), &actions());
+        let e = apply(&SOURCE, &patch).unwrap_err();
+        assert!(e.contains("the patched ROM's checksum"), "{}", e);
+    }
+
+    #[test]
+    fn bps_patch_crc() {
+        let mut patch = bps(&SOURCE, TARGET.len(), crc32fast::hash(&TARGET), &actions());
+        let last = patch.len() - 1;
+        patch[last] ^= 1;
+        let e = apply(&SOURCE, &patch).unwrap_err();
+        assert!(e.contains("damaged"), "{}", e);
+    }
+
+    #[test]
+    fn bps_past_memory() {
+        let patch = bps(&SOURCE, MEMORY_SIZE + 1, 0, &[]);
+        let e = apply(&SOURCE, &patch).unwrap_err();
+        assert!(e.contains("more than the 4096 bytes"), "{}", e);
+    }
+}
@@ -0,0 +1,114 @@
+// Behaviours that differ between CHIP-8 interpreters. ROMs written for one
+// of them often misbehave on the others.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VX in place instead of shifting VY into VX
+    pub shift: bool,
+    // FX55/FX65 leave I unchanged instead of moving it past the registers
+    pub load_store: bool,
+    // BNNN jumps to NNN + VX (X being the top digit of NNN) instead of V0
+    pub jump: bool,
+    // 8XY1/8XY2/8XY3 reset VF to 0
+    pub vf_reset: bool,
+    // sprites are cut off at the screen edges instead of wrapping around
+    pub clip: bool,
+}
+
+// Names for `Quirks::set`, e.g. in `--quirk shift=off`.
+pub static NAMES: [&str; 5] = ["shift", "load-store", "jump", "vf-reset", "clip"];
+
+impl Quirks {
+    pub fn set(&mut self, name: &str, on: bool) -> Result<(), String> {
+        match name {
+            "shift" => self.shift = on,
+            "load-store" => self.load_store = on,
+            "jump" => self.jump = on,
+            "vf-reset" => self.vf_reset = on,
+            "clip" => self.clip = on,
+            _ => {
+                return Err(format!(
+                    "unknown quirk `{}`, expected one of {}",
+                    name,
+                    NAMES.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    // `<name>=on` or `<name>=off`
+    pub fn parse_override(s: &str) -> Result<(String, bool), String> {
+        let mut parts = s.splitn(2, '=');
+        let name = parts.next().unwrap_or("").trim();
+        let on = match parts.next().map(|v| v.trim()) {
+            Some("on") | Some("true") | Some("1") => true,
+            Some("off") | Some("false") | Some("0") => false,
+            _ => return Err(format!("expected <quirk>=on|off, got `{}`", s)),
+        };
+        Quirks::default().set(name, on)?;
+        Ok((name.to_string(), on))
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Platform::Modern.quirks()
+    }
+}
+
+// Quirk presets for the interpreters ROMs are usually written for. Only the
+// quirks differ; the SUPER-CHIP and XO-CHIP instructions are not emulated.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Platform {
+    // what this emulator has always done
+    Modern,
+    // the original interpreter on the COSMAC VIP
+    Vip,
+    // SUPER-CHIP 1.1 on the HP 48
+    Schip,
+}
+
+impl Platform {
+    pub fn parse(s: &str) -> Option<Platform> {
+        match s {
+            "modern" => Some(Platform::Modern),
+            "vip" | "chip8" => Some(Platform::Vip),
+            "schip" | "superchip" => Some(Platform::Schip),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::Modern => "modern",
+            Platform::Vip => "vip",
+            Platform::Schip => "schip",
+        }
+    }
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Modern => Quirks {
+                shift: true,
+                load_store: true,
+                jump: false,
+                vf_reset: false,
+                clip: false,
+            },
+            Platform::Vip => Quirks {
+                shift: false,
+                load_store: false,
+                jump: false,
+                vf_reset: true,
+                clip: true,
+            },
+            Platform::Schip => Quirks {
+                shift: true,
+                load_store: true,
+                jump: true,
+                vf_reset: false,
+                clip: true,
+            },
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Where the recently played ROMs are kept, one path a line.
+pub static RECENT: &str = "recent.txt";
+// How many are kept.
+pub static MAX: usize = 10;
+
+// The ROMs played last, newest first.
+pub struct Recent {
+    path: PathBuf,
+    roms: Vec<String>,
+}
+
+impl Recent {
+    // Reads the list from `path`, which is empty when there is no file yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> Recent {
+        let path = path.as_ref().to_path_buf();
+        let roms = match fs::read_to_string(&path) {
+            Ok(text) => text
+                .lines()
+                .map(|l| l.trim())
+                .filter(|l| !l.is_empty())
+                .take(MAX)
+                .map(|l| l.to_string())
+                .collect(),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                eprintln!("Error Reading {}: {}", path.display(), e);
+                Vec::new()
+            }
+        };
+        Recent { path, roms }
+    }
+
+    pub fn roms(&self) -> &[String] {
+        &self.roms
+    }
+
+    // Puts `rom` first, by its full path so that it still works from
+    // another directory, dropping the oldest past MAX.
+    pub fn add(&mut self, rom: &str) {
+        let rom = match fs::canonicalize(rom) {
+            Ok(path) => path.to_string_lossy().into_owned(),
+            Err(_) => rom.to_string(),
+        };
+        self.roms.retain(|r| *r != rom);
+        self.roms.insert(0, rom);
+        self.roms.truncate(MAX);
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let mut text = String::new();
+        for rom in &self.roms {
+            text.push_str(rom);
+            text.push('\n');
+        }
+        fs::write(&self.path, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn newest_first_without_repeats() {
+        let mut recent = Recent::load("no-such-recent.txt");
+        assert!(recent.roms().is_empty());
+        for i in 0..MAX + 2 {
+            recent.add(&format!("no-such-rom-{}.ch8", i));
+        }
+        recent.add("no-such-rom-5.ch8");
+
+        assert_eq!(recent.roms().len(), MAX);
+        assert_eq!(recent.roms()[0], "no-such-rom-5.ch8");
+        assert_eq!(recent.roms()[1], format!("no-such-rom-{}.ch8", MAX + 1));
+        assert_eq!(
+            recent
+                .roms()
+                .iter()
+                .filter(|r| r.ends_with("-5.ch8"))
+                .count(),
+            1
+        );
+        // the oldest went
+        assert!(!recent.roms().contains(&"no-such-rom-1.ch8".to_string()));
+    }
+
+    #[test]
+    fn saved_and_loaded() {
+        let dir = env::temp_dir().join(format!("chip8-recent-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("game.ch8");
+        fs::write(&rom, [0x12, 0x00]).unwrap();
+        let path = dir.join("recent.txt");
+
+        let mut recent = Recent::load(&path);
+        recent.add("no-such-rom.ch8");
+        // files that exist are kept by their full path
+        recent.add(&rom.to_string_lossy());
+        recent.save().unwrap();
+
+        let loaded = Recent::load(&path);
+        let full = fs::canonicalize(&rom).unwrap();
+        assert_eq!(
+            loaded.roms(),
+            &[
+                full.to_string_lossy().into_owned(),
+                "no-such-rom.ch8".to_string()
+            ]
+        );
+
+        // blank lines are skipped and only MAX are read
+        let lines: Vec<String> = (0..MAX + 5).map(|i| format!("{}.ch8\n\n", i)).collect();
+        fs::write(&path, lines.concat()).unwrap();
+        let loaded = Recent::load(&path);
+        assert_eq!(loaded.roms().len(), MAX);
+        assert_eq!(loaded.roms()[0], "0.ch8");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
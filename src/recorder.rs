@@ -0,0 +1,243 @@
+use gif;
+use gpu::{Gpu, HEIGHT, WIDTH};
+use palette::Palette;
+use std::io;
+use std::io::prelude::*;
+
+// Frames are captured at a fixed 60 per second of emulated time, which
+// keeps recordings independent of how fast the host runs.
+pub const FPS: u64 = 60;
+
+// Viewers treat anything shorter than 2/100s as 10/100s.
+const MIN_DELAY: u64 = 2;
+
+pub trait Recorder {
+    fn capture(&mut self, gpu: &Gpu) -> io::Result<()>;
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+// Animated GIF. Identical frames are merged into one with a longer delay.
+pub struct GifRecorder<W: Write> {
+    encoder: gif::Encoder<W>,
+    scale: usize,
+    frames: u64,
+    // the frame waiting for its delay, and when it first appeared
+    pending: Option<(Vec<u8>, u64)>,
+}
+
+impl<W: Write> GifRecorder<W> {
+    pub fn new(w: W, scale: usize, palette: &Palette) -> io::Result<GifRecorder<W>> {
+        let palette: Vec<u8> = palette.colours.iter().flat_map(|c| c.to_vec()).collect();
+
+        let mut encoder =
+            gif::Encoder::new(w, (WIDTH * scale) as u16, (HEIGHT * scale) as u16, &palette)
+                .map_err(io::Error::other)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(io::Error::other)?;
+
+        Ok(GifRecorder {
+            encoder,
+            scale,
+            frames: 0,
+            pending: None,
+        })
+    }
+
+    fn write_pending(&mut self, until: u64) -> io::Result<()> {
+        let (pixels, since) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+
+        let mut delay = centiseconds(until) - centiseconds(since);
+        let mut frame = gif::Frame::from_indexed_pixels(
+            (WIDTH * self.scale) as u16,
+            (HEIGHT * self.scale) as u16,
+            scale_up(&pixels, self.scale),
+            None,
+        );
+
+        // a frame shows for at most u16::MAX centiseconds, longer still
+        // screens are repeated
+        while delay > u16::MAX as u64 {
+            frame.delay = u16::MAX;
+            self.encoder.write_frame(&frame).map_err(io::Error::other)?;
+            delay -= u16::MAX as u64;
+        }
+        frame.delay = delay.max(MIN_DELAY) as u16;
+
+        self.encoder.write_frame(&frame).map_err(io::Error::other)
+    }
+}
+
+impl<W: Write> Recorder for GifRecorder<W> {
+    fn capture(&mut self, gpu: &Gpu) -> io::Result<()> {
+        let now = self.frames;
+        self.frames += 1;
+
+        let since = match self.pending {
+            Some((ref pixels, _)) if pixels.as_slice() == gpu.pixels() => return Ok(()),
+            Some((_, since)) => since,
+            None => {
+                self.pending = Some((gpu.pixels().to_vec(), now));
+                return Ok(());
+            }
+        };
+
+        // too short to show, let the new frame take over its time
+        if centiseconds(now) - centiseconds(since) < MIN_DELAY {
+            self.pending = Some((gpu.pixels().to_vec(), since));
+            return Ok(());
+        }
+
+        self.write_pending(now)?;
+        self.pending = Some((gpu.pixels().to_vec(), now));
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let end = self.frames;
+        self.write_pending(end)
+    }
+}
+
+// Every captured frame as raw 24-bit RGB, for piping into an encoder, e.g.
+// `ffmpeg -f rawvideo -pix_fmt rgb24 -s 640x320 -r 60 -i - out.mp4`.
+pub struct RawRecorder<W: Write> {
+    w: W,
+    scale: usize,
+    palette: Palette,
+}
+
+impl<W: Write> RawRecorder<W> {
+    pub fn new(w: W, scale: usize, palette: &Palette) -> RawRecorder<W> {
+        RawRecorder {
+            w,
+            scale,
+            palette: palette.clone(),
+        }
+    }
+}
+
+impl<W: Write> Recorder for RawRecorder<W> {
+    fn capture(&mut self, gpu: &Gpu) -> io::Result<()> {
+        let mut rgb = Vec::with_capacity(WIDTH * HEIGHT * self.scale * self.scale * 3);
+        for p in scale_up(gpu.pixels(), self.scale) {
+            rgb.extend_from_slice(&self.palette.colour(p));
+        }
+        self.w.write_all(&rgb)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+fn centiseconds(frame: u64) -> u64 {
+    frame * 100 / FPS
+}
+
+fn scale_up(pixels: &[u8], scale: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(pixels.len() * scale * scale);
+    for row in pixels.chunks(WIDTH) {
+        for _ in 0..scale {
+            for &p in row {
+                for _ in 0..scale {
+                    out.push(p);
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The delay of every frame in a GIF.
+    fn delays(data: &[u8]) -> Vec<u16> {
+        let mut decoder = gif::DecodeOptions::new().read_info(data).unwrap();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        delays
+    }
+
+    #[test]
+    fn identical_frames_merge() {
+        let mut gpu = Gpu::new();
+        let mut out = Vec::new();
+        {
+            let mut recorder = GifRecorder::new(&mut out, 1, &Palette::default()).unwrap();
+            for _ in 0..120 {
+                recorder.capture(&gpu).unwrap();
+            }
+            gpu.draw_sprite(0, 0, &[0x80]);
+            for _ in 0..30 {
+                recorder.capture(&gpu).unwrap();
+            }
+            recorder.finish().unwrap();
+        }
+        // two seconds, then half a second
+        assert_eq!(delays(&out), vec![200, 50]);
+    }
+
+    #[test]
+    fn short_frames_give_way() {
+        let mut gpu = Gpu::new();
+        let mut out = Vec::new();
+        {
+            let mut recorder = GifRecorder::new(&mut out, 1, &Palette::default()).unwrap();
+            // one 60th of a second is under the shortest delay
+            recorder.capture(&gpu).unwrap();
+            gpu.draw_sprite(0, 0, &[0x80]);
+            for _ in 0..59 {
+                recorder.capture(&gpu).unwrap();
+            }
+            recorder.finish().unwrap();
+        }
+        assert_eq!(delays(&out), vec![100]);
+        assert_eq!(centiseconds(1), 1);
+        assert_eq!(centiseconds(3), 5);
+    }
+
+    #[test]
+    fn long_delays_split() {
+        let mut out = Vec::new();
+        {
+            let mut recorder = GifRecorder::new(&mut out, 1, &Palette::default()).unwrap();
+            recorder.pending = Some((Gpu::new().pixels().to_vec(), 0));
+            // 1000 seconds
+            recorder.write_pending(60_000).unwrap();
+        }
+        assert_eq!(delays(&out), vec![u16::MAX, 34465]);
+    }
+
+    #[test]
+    fn raw_rgb() {
+        let palette = Palette::default();
+        let mut gpu = Gpu::new();
+        gpu.draw_sprite(0, 0, &[0x80]);
+        let mut out = Vec::new();
+        {
+            let mut recorder = RawRecorder::new(&mut out, 2, &palette);
+            recorder.capture(&gpu).unwrap();
+            recorder.capture(&gpu).unwrap();
+            recorder.finish().unwrap();
+        }
+
+        let frame = WIDTH * 2 * HEIGHT * 2 * 3;
+        assert_eq!(out.len(), frame * 2);
+        let (lit, off) = (palette.colour(1), palette.colour(0));
+        let row = WIDTH * 2 * 3;
+        for start in &[0, 3, row, row + 3] {
+            assert_eq!(out[*start..*start + 3], lit);
+        }
+        assert_eq!(out[6..9], off);
+        assert_eq!(out[row * 2..row * 2 + 3], off);
+        assert_eq!(out[..frame], out[frame..]);
+    }
+}
@@ -0,0 +1,275 @@
+use config::Profile;
+use cpu::{LoadError, MEMORY_SIZE};
+use octo;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use zip::ZipArchive;
+
+// The most that is read of an Octo options file in an archive.
+static OPTIONS_SIZE: usize = 0x10000;
+
+// File names ROMs usually have.
+pub static EXTENSIONS: [&str; 5] = ["ch8", "c8", "rom", "sc8", "xo8"];
+
+// Whether `path` is named like a ROM or a zip archive.
+pub fn is_rom_file<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .is_some_and(|e| e == "zip" || EXTENSIONS.contains(&e.as_str()))
+}
+
+// A program to load, with the settings that came with it.
+pub struct Rom {
+    pub data: Vec<u8>,
+    pub profile: Option<Profile>,
+}
+
+// Reads a ROM from a plain ROM file or a zip archive with one in it, and
+// the Octo options next to it: in the archive, or in a `.json` file with
+// the same name as the ROM.
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Rom, LoadError> {
+    let mut rom = unpack(fs::read(&path)?)?;
+    let options = path.as_ref().with_extension("json");
+    if rom.profile.is_none() && options.exists() {
+        rom.profile = octo::load_options(&options);
+    }
+    Ok(rom)
+}
+
+pub fn unpack(data: Vec<u8>) -> Result<Rom, LoadError> {
+    if data.starts_with(b"PK\x03\x04") {
+        return unzip(&data);
+    }
+    if octo::is_gif(&data) {
+        return cartridge(&data);
+    }
+    Ok(Rom {
+        data,
+        profile: None,
+    })
+}
+
+// The ROM is the archive's only file, or its only file with a ROM
+// extension.
+fn unzip(data: &[u8]) -> Result<Rom, LoadError> {
+    let format = |e: ::zip::result::ZipError| LoadError::Format(e.to_string());
+    let mut archive = ZipArchive::new(Cursor::new(data)).map_err(format)?;
+
+    let files: Vec<String> = archive
+        .file_names()
+        .filter(|name| !name.ends_with('/'))
+        .map(|name| name.to_string())
+        .collect();
+    let roms: Vec<&String> = files
+        .iter()
+        .filter(|name| {
+            let extension = Path::new(name).extension().and_then(|e| e.to_str());
+            extension.is_some_and(|e| EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        })
+        .collect();
+    let name = match (files.len(), roms.len()) {
+        (1, _) => &files[0],
+        (_, 1) => roms[0],
+        (_, 0) => return Err(LoadError::Format("no ROM in the archive".to_string())),
+        _ => {
+            return Err(LoadError::Format(format!(
+                "more than one ROM in the archive: {}",
+                roms.iter()
+                    .map(|r| r.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )))
+        }
+    };
+
+    let rom = read_entry(&mut archive, name, MEMORY_SIZE)?;
+    // an archive in the archive could go on nesting
+    if rom.starts_with(b"PK\x03\x04") {
+        return Err(LoadError::Format(format!(
+            "{} in the archive is another archive",
+            name
+        )));
+    }
+    let mut rom = unpack(rom)?;
+
+    // Octo options that came with it
+    let json: Vec<&String> = files.iter().filter(|n| n.ends_with(".json")).collect();
+    if json.len() == 1 {
+        match read_entry(&mut archive, json[0], OPTIONS_SIZE)
+            .map_err(|e| e.to_string())
+            .and_then(|options| octo::read_options(&options))
+        {
+            Ok((profile, warnings)) => {
+                for warning in warnings {
+                    eprintln!("{}: {}", json[0], warning);
+                }
+                rom.profile = Some(profile);
+            }
+            Err(e) => eprintln!("Error Reading {}: {}", json[0], e),
+        }
+    }
+    Ok(rom)
+}
+
+// Reads a file from the archive, refusing it past `limit` bytes whatever
+// size the archive claims it has.
+fn read_entry(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    name: &str,
+    limit: usize,
+) -> Result<Vec<u8>, LoadError> {
+    let mut data = Vec::new();
+    archive
+        .by_name(name)
+        .map_err(|e| LoadError::Format(e.to_string()))?
+        .take(limit as u64 + 1)
+        .read_to_end(&mut data)?;
+    if data.len() > limit {
+        return Err(LoadError::Format(format!(
+            "{} in the archive is larger than {} bytes",
+            name, limit
+        )));
+    }
+    Ok(data)
+}
+
+// Cartridges hold Octo source rather than a ROM, and assembling Octo is out
+// of scope here, so all that can be done is say so. octo::read_cartridge
+// still gives the source and options to anything that can assemble them.
+fn cartridge(data: &[u8]) -> Result<Rom, LoadError> {
+    let cartridge = octo::read_cartridge(data).map_err(LoadError::Format)?;
+    Err(LoadError::Format(format!(
+        "the Octo cartridge holds {} bytes of Octo source, which needs assembling in Octo first",
+        cartridge.program.len()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gif;
+    use serde_json;
+    use std::io::Write;
+    use zip::write::{FileOptions, ZipWriter};
+    use zip::CompressionMethod;
+
+    fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        for &(name, data) in files {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn rom_from_archive() {
+        let data = archive(&[("readme.txt", b"hi"), ("game.ch8", &[0x12, 0x00])]);
+        assert_eq!(unpack(data).unwrap().data, [0x12, 0x00]);
+    }
+
+    #[test]
+    fn rom_files_by_extension() {
+        assert!(is_rom_file("games/pong2.c8"));
+        assert!(is_rom_file("GAMES.ZIP"));
+        assert!(!is_rom_file("notes.txt"));
+        assert!(!is_rom_file("ch8"));
+    }
+
+    #[test]
+    fn nested_archive_refused() {
+        let inner = archive(&[("game.ch8", &[0x12, 0x00])]);
+        let outer = archive(&[("game.ch8", &inner)]);
+        match unpack(outer) {
+            Err(LoadError::Format(e)) => assert!(e.contains("another archive"), "{}", e),
+            _ => panic!("nested archive loaded"),
+        }
+    }
+
+    // An Octo cartridge: a 16 colour label, with the payload in the low
+    // four bits of every pixel across two frames.
+    fn cartridge_gif(json: &str) -> Vec<u8> {
+        let mut payload = (json.len() as u32).to_be_bytes().to_vec();
+        payload.extend_from_slice(json.as_bytes());
+        let nibbles: Vec<u8> = payload.iter().flat_map(|b| vec![b >> 4, b & 0xF]).collect();
+
+        let (width, height) = (32, 16);
+        let palette: Vec<u8> = (0..=255).flat_map(|i| vec![i, i, i]).collect();
+        let mut gif = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut gif, width, height, &palette).unwrap();
+            for chunk in nibbles.chunks(width as usize * height as usize) {
+                let mut pixels: Vec<u8> = (0..width as usize * height as usize)
+                    // the label shows in the high bits
+                    .map(|i| if i % 3 == 0 { 0xF0 } else { 0x20 })
+                    .collect();
+                for (p, &n) in pixels.iter_mut().zip(chunk) {
+                    *p |= n;
+                }
+                let frame = gif::Frame::from_indexed_pixels(width, height, pixels, None);
+                encoder.write_frame(&frame).unwrap();
+            }
+        }
+        gif
+    }
+
+    #[test]
+    fn cartridge_read_but_not_loaded() {
+        let program = format!(": main\n{}  loop again\n", "  v0 += 1\n".repeat(30));
+        let json = serde_json::json!({
+            "program": program,
+            "options": { "tickrate": 20, "shiftQuirks": true },
+        })
+        .to_string();
+        let gif = cartridge_gif(&json);
+        assert!(
+            json.len() * 2 > 32 * 16,
+            "the payload should take two frames"
+        );
+
+        let cartridge = octo::read_cartridge(&gif).unwrap();
+        assert_eq!(cartridge.program, program);
+        let (profile, warnings) = octo::profile(&cartridge.options).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(profile.speed, Some(20));
+        assert_eq!(profile.quirks, vec![("shift".to_string(), true)]);
+
+        match unpack(gif) {
+            Err(LoadError::Format(e)) => {
+                assert!(
+                    e.contains(&format!("{} bytes of Octo source", program.len())),
+                    "{}",
+                    e
+                )
+            }
+            _ => panic!("a cartridge loaded as a ROM"),
+        }
+    }
+
+    #[test]
+    fn plain_gif_is_not_a_cartridge() {
+        let mut gif = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut gif, 2, 2, &[0, 0, 0, 255, 255, 255]).unwrap();
+            let frame = gif::Frame::from_indexed_pixels(2, 2, vec![0, 1, 1, 0], None);
+            encoder.write_frame(&frame).unwrap();
+        }
+        match unpack(gif) {
+            Err(LoadError::Format(e)) => assert_eq!(e, "not an Octo cartridge"),
+            _ => panic!("a plain GIF loaded"),
+        }
+    }
+
+    #[test]
+    fn oversized_entry_refused() {
+        let data = archive(&[("game.ch8", &[0; 4097])]);
+        match unpack(data) {
+            Err(LoadError::Format(e)) => assert!(e.contains("larger than 4096"), "{}", e),
+            _ => panic!("oversized ROM loaded"),
+        }
+    }
+}
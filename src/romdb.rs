@@ -0,0 +1,186 @@
+use config::{self, Profile};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use toml_edit::{DocumentMut, Table, TableLike};
+
+static BUILTIN: &str = include_str!("romdb.toml");
+
+// What is known about a ROM.
+#[derive(Clone, Default)]
+pub struct RomInfo {
+    pub title: String,
+    pub author: Option<String>,
+    // the settings the ROM needs, unless the config file or the command
+    // line say otherwise
+    pub profile: Profile,
+    // what the keypad buttons do
+    pub hints: Vec<(usize, String)>,
+}
+
+impl RomInfo {
+    // The title and author, then a line per hint.
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = vec![match self.author {
+            Some(ref author) => format!("{} by {}", self.title, author),
+            None => self.title.clone(),
+        }];
+        for &(button, ref hint) in &self.hints {
+            lines.push(format!("  {:X}  {}", button, hint));
+        }
+        lines
+    }
+}
+
+// ROM information by the SHA-1 of the ROM, in the same TOML as the
+// profiles of a config file with a title, author and key hints added:
+//
+//     [5c28a5f85289c9d859f95fd5eadbdcb1c30bb08b]
+//     title = "Space Invaders"
+//     author = "David Winter"
+//     speed = 10
+//     hints = { 4 = "left", 5 = "fire", 6 = "right" }
+pub struct RomDb {
+    roms: HashMap<String, RomInfo>,
+}
+
+impl RomDb {
+    pub fn new() -> RomDb {
+        RomDb {
+            roms: HashMap::new(),
+        }
+    }
+
+    // The database that comes with the emulator.
+    pub fn builtin() -> RomDb {
+        RomDb::parse(BUILTIN).expect("the built-in ROM database is broken")
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<RomDb> {
+        let s = fs::read_to_string(path)?;
+        RomDb::parse(&s).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn parse(s: &str) -> Result<RomDb, String> {
+        let doc = s.parse::<DocumentMut>().map_err(|e| e.to_string())?;
+        let mut db = RomDb::new();
+        for (hash, item) in doc.iter() {
+            if hash.len() != 40 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("`{}` is not a SHA-1", hash));
+            }
+            let table = item
+                .as_table_like()
+                .ok_or_else(|| format!("{} is not a table", hash))?;
+            let info = read_info(table).map_err(|e| format!("[{}]: {}", hash, e))?;
+            db.roms.insert(hash.to_lowercase(), info);
+        }
+        Ok(db)
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&RomInfo> {
+        self.roms.get(hash)
+    }
+
+    // Adds the ROMs of `other`, replacing the ones already here.
+    pub fn extend(&mut self, other: RomDb) {
+        self.roms.extend(other.roms);
+    }
+}
+
+impl Default for RomDb {
+    fn default() -> RomDb {
+        RomDb::new()
+    }
+}
+
+fn read_info(table: &dyn TableLike) -> Result<RomInfo, String> {
+    let mut info = RomInfo::default();
+    // the settings are left to the config file's reader
+    let mut settings = Table::new();
+    for (key, item) in table.iter() {
+        match key {
+            "title" => info.title = item.as_str().ok_or("title must be a string")?.to_string(),
+            "author" => {
+                info.author = Some(item.as_str().ok_or("author must be a string")?.to_string())
+            }
+            "hints" => {
+                let hints = item.as_table_like().ok_or("hints must be a table")?;
+                for (button, hint) in hints.iter() {
+                    let b = usize::from_str_radix(button, 16)
+                        .ok()
+                        .filter(|&b| b < 16)
+                        .ok_or_else(|| format!("unknown keypad button `{}`", button))?;
+                    let hint = hint
+                        .as_str()
+                        .ok_or_else(|| format!("hint for `{}` must be a string", button))?;
+                    info.hints.push((b, hint.to_string()));
+                }
+            }
+            _ => {
+                settings.insert(key, item.clone());
+            }
+        }
+    }
+    if info.title.is_empty() {
+        return Err("title is missing".to_string());
+    }
+    config::read_table(&settings, &mut info.profile)?;
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quirks::Platform;
+
+    static PONG2: &str = "1830eb401ba8789a477dfcf294873a5479ebcfe8";
+
+    #[test]
+    fn builtin_lookup() {
+        let db = RomDb::builtin();
+        let pong = db.get(PONG2).unwrap();
+        assert_eq!(pong.describe()[0], "Pong 2 by David Winter");
+        assert_eq!(pong.profile.platform, Some(Platform::Vip));
+        assert!(db.get(&"0".repeat(40)).is_none());
+    }
+
+    #[test]
+    fn later_databases_win() {
+        let mut db = RomDb::builtin();
+        let more = format!(
+            "[{}]\ntitle = \"My Pong\"\nspeed = 12\nhints = {{ 1 = \"up\" }}\n",
+            PONG2.to_uppercase()
+        );
+        db.extend(RomDb::parse(&more).unwrap());
+        let pong = db.get(PONG2).unwrap();
+        assert_eq!(pong.describe(), vec!["My Pong", "  1  up"]);
+        // a whole entry is replaced, not merged
+        assert_eq!(pong.profile.platform, None);
+        assert_eq!(pong.profile.speed, Some(12));
+    }
+
+    #[test]
+    fn bad_entries() {
+        assert_eq!(
+            RomDb::parse("[pong]\ntitle = \"Pong\"").err().unwrap(),
+            "`pong` is not a SHA-1"
+        );
+        let hash = "0".repeat(40);
+        assert_eq!(
+            RomDb::parse(&format!("[{}]\nspeed = 5", hash))
+                .err()
+                .unwrap(),
+            format!("[{}]: title is missing", hash)
+        );
+        assert_eq!(
+            RomDb::parse(&format!(
+                "[{}]\ntitle = \"X\"\nhints = {{ G = \"up\" }}",
+                hash
+            ))
+            .err()
+            .unwrap(),
+            format!("[{}]: unknown keypad button `G`", hash)
+        );
+    }
+}
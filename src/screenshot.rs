@@ -0,0 +1,106 @@
+use gpu::{Gpu, HEIGHT, WIDTH};
+use palette::Palette;
+use png;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+// Raw PBM (P4) at the native resolution, one bit per pixel with lit pixels
+// as 1. This only looks at the framebuffer, so it is what golden-image
+// tests should compare against.
+pub fn write_pbm<W: Write>(gpu: &Gpu, w: &mut W) -> io::Result<()> {
+    write!(w, "P4\n{} {}\n", WIDTH, HEIGHT)?;
+
+    for y in 0..HEIGHT {
+        let mut row = vec![0u8; WIDTH.div_ceil(8)];
+        for x in 0..WIDTH {
+            if gpu.pixel(x, y) {
+                row[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        w.write_all(&row)?;
+    }
+
+    Ok(())
+}
+
+// RGB PNG scaled up by `scale`, in the palette's colours.
+pub fn write_png<W: Write>(
+    gpu: &Gpu,
+    scale: usize,
+    palette: &Palette,
+    w: &mut W,
+) -> io::Result<()> {
+    let (width, height) = (WIDTH * scale, HEIGHT * scale);
+    let mut data = Vec::with_capacity(width * height * 4);
+
+    for y in 0..height {
+        for x in 0..width {
+            let p = gpu.pixels()[(y / scale) * WIDTH + x / scale];
+            data.extend_from_slice(&palette.colour(p));
+            data.push(255);
+        }
+    }
+
+    write_rgba_png(&data, width, height, w)
+}
+
+// PNG of an RGBA image, e.g. the output of the display filters or the CRT
+// effects, so they can be checked without a window.
+pub fn write_rgba_png<W: Write>(
+    rgba: &[u8],
+    width: usize,
+    height: usize,
+    w: &mut W,
+) -> io::Result<()> {
+    let rgb: Vec<u8> = rgba
+        .chunks(4)
+        .flat_map(|p| p[..3].iter().cloned())
+        .collect();
+
+    let mut encoder = png::Encoder::new(w, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&rgb)?;
+
+    Ok(())
+}
+
+// Picks the format from the extension: `.pbm` is native, anything else a
+// PNG scaled by `scale`.
+pub fn save<P: AsRef<Path>>(gpu: &Gpu, scale: usize, palette: &Palette, path: P) -> io::Result<()> {
+    let mut f = File::create(&path)?;
+    match path.as_ref().extension().and_then(|e| e.to_str()) {
+        Some("pbm") => write_pbm(gpu, &mut f),
+        _ => write_png(gpu, scale, palette, &mut f),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pbm_bytes() {
+        let mut gpu = Gpu::new();
+        // a lit pixel in each corner and a full byte at the start of row 1
+        gpu.draw_sprite(0, 0, &[0x80, 0xFF]);
+        gpu.draw_sprite(WIDTH - 8, 0, &[0x01]);
+        gpu.draw_sprite(0, HEIGHT - 1, &[0x80]);
+        gpu.draw_sprite(WIDTH - 8, HEIGHT - 1, &[0x01]);
+
+        let mut out = Vec::new();
+        write_pbm(&gpu, &mut out).unwrap();
+
+        let mut expected = b"P4\n64 32\n".to_vec();
+        let mut rows = vec![0u8; 8 * 32];
+        rows[0] = 0x80;
+        rows[7] = 0x01;
+        rows[8] = 0xFF;
+        rows[31 * 8] = 0x80;
+        rows[31 * 8 + 7] = 0x01;
+        expected.extend_from_slice(&rows);
+        assert_eq!(out, expected);
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+pub struct Symbol {
+    pub name: String,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+// Address to name mapping, loaded from a symbol file where every line is
+//
+//     <address> <name> [<file>[:<line>]]
+//
+// with the address in hex (an optional `0x` or `$` prefix is accepted).
+// Blank lines and lines starting with `#` or `;` are ignored.
+pub struct Symbols {
+    symbols: BTreeMap<usize, Symbol>,
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols {
+            symbols: BTreeMap::new(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Symbols> {
+        let mut s = String::new();
+        File::open(path)?.read_to_string(&mut s)?;
+        Symbols::parse(&s)
+    }
+
+    // Looks for `<rom>.sym` next to the ROM, e.g. `games/pong2.sym`.
+    pub fn load_for_rom<P: AsRef<Path>>(rom: P) -> Option<Symbols> {
+        let path = rom.as_ref().with_extension("sym");
+        if !path.exists() {
+            return None;
+        }
+
+        match Symbols::load(&path) {
+            Ok(symbols) => Some(symbols),
+            Err(e) => {
+                eprintln!("Error Reading {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    pub fn parse(s: &str) -> io::Result<Symbols> {
+        let mut symbols = Symbols::new();
+
+        for (n, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            let addr = parts.next().and_then(parse_addr);
+            let name = parts.next();
+            let (addr, name) = match (addr, name) {
+                (Some(addr), Some(name)) => (addr, name),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("bad symbol on line {}: {}", n + 1, line),
+                    ))
+                }
+            };
+
+            let (file, line) = match parts.next() {
+                Some(source) => match source.rfind(':') {
+                    Some(p) => match source[p + 1..].parse() {
+                        Ok(line) => (Some(source[..p].to_string()), Some(line)),
+                        Err(_) => (Some(source.to_string()), None),
+                    },
+                    None => (Some(source.to_string()), None),
+                },
+                None => (None, None),
+            };
+
+            symbols.symbols.insert(
+                addr,
+                Symbol {
+                    name: name.to_string(),
+                    file,
+                    line,
+                },
+            );
+        }
+
+        Ok(symbols)
+    }
+
+    pub fn get(&self, addr: usize) -> Option<&Symbol> {
+        self.symbols.get(&addr)
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<usize> {
+        self.symbols
+            .iter()
+            .find(|&(_, s)| s.name == name)
+            .map(|(&addr, _)| addr)
+    }
+
+    // The symbol name, or an auto-generated label for unknown addresses.
+    pub fn label(&self, addr: usize) -> String {
+        match self.get(addr) {
+            Some(s) => s.name.clone(),
+            None => format!("L{:03X}", addr),
+        }
+    }
+}
+
+impl Default for Symbols {
+    fn default() -> Symbols {
+        Symbols::new()
+    }
+}
+
+fn parse_addr(s: &str) -> Option<usize> {
+    let s = s.trim_start_matches("0x").trim_start_matches('$');
+    usize::from_str_radix(s, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_formats() {
+        let symbols = Symbols::parse("0x200 start\n$20A loop\n214 draw\n").unwrap();
+        assert_eq!(symbols.lookup("start"), Some(0x200));
+        assert_eq!(symbols.lookup("loop"), Some(0x20A));
+        assert_eq!(symbols.lookup("draw"), Some(0x214));
+        assert_eq!(symbols.lookup("missing"), None);
+    }
+
+    #[test]
+    fn optional_source() {
+        let symbols = Symbols::parse(
+            "200 start game.8o:12\n202 loop game.8o\n204 draw game.8o:end\n210 end\n",
+        )
+        .unwrap();
+        let start = symbols.get(0x200).unwrap();
+        assert_eq!(start.file.as_deref(), Some("game.8o"));
+        assert_eq!(start.line, Some(12));
+        let lp = symbols.get(0x202).unwrap();
+        assert_eq!(lp.file.as_deref(), Some("game.8o"));
+        assert_eq!(lp.line, None);
+        let draw = symbols.get(0x204).unwrap();
+        assert_eq!(draw.file.as_deref(), Some("game.8o:end"));
+        assert_eq!(draw.line, None);
+        let end = symbols.get(0x210).unwrap();
+        assert!(end.file.is_none() && end.line.is_none());
+    }
+
+    #[test]
+    fn comments_and_errors() {
+        let symbols = Symbols::parse("# a comment\n\n; another\n  200 start\n").unwrap();
+        assert_eq!(symbols.lookup("start"), Some(0x200));
+
+        assert!(Symbols::parse("200 start\nzzz nope\n").is_err());
+        assert!(Symbols::parse("200\n").is_err());
+    }
+
+    #[test]
+    fn fallback_labels() {
+        let symbols = Symbols::parse("200 start\n").unwrap();
+        assert_eq!(symbols.label(0x200), "start");
+        assert_eq!(symbols.label(0x2A4), "L2A4");
+        assert_eq!(symbols.label(0x10), "L010");
+    }
+}
@@ -0,0 +1,51 @@
+use cpu::Cpu;
+use disasm::disassemble;
+use std::io;
+use std::io::prelude::*;
+use symbols::Symbols;
+
+// Writes the instruction the Cpu is about to run as one line: the cycle,
+// the address, the opcode and its disassembly, like the debugger shows it.
+// A named address gets a label line first.
+pub fn write<W: Write>(w: &mut W, cpu: &Cpu, symbols: Option<&Symbols>) -> io::Result<()> {
+    let pc = cpu.pc();
+    let memory = cpu.memory();
+    let opcode = (memory[pc] as u16) << 8 | memory[(pc + 1) % memory.len()] as u16;
+
+    if let Some(label) = symbols.and_then(|s| s.get(pc)) {
+        writeln!(w, "{}:", label.name)?;
+    }
+    writeln!(
+        w,
+        "{:>8}  {:03X}  {:04X}  {}",
+        cpu.cycles(),
+        pc,
+        opcode,
+        disassemble(opcode, cpu.quirks(), symbols)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_line_per_instruction() {
+        let mut cpu = Cpu::new();
+        // CALL 0x206, then LD V1, 0x02 there
+        cpu.load_bytes(&[0x22, 0x06, 0x00, 0x00, 0x00, 0x00, 0x61, 0x02])
+            .unwrap();
+        let symbols = Symbols::parse("206 setup\n").unwrap();
+
+        let mut out = Vec::new();
+        for _ in 0..2 {
+            write(&mut out, &cpu, Some(&symbols)).unwrap();
+            cpu.emulate_cycle();
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "       0  200  2206  CALL setup\n\
+             setup:\n       1  206  6102  LD V1, 0x02\n"
+        );
+    }
+}
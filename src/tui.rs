@@ -0,0 +1,82 @@
+use cpu::Cpu;
+use gpu::{Gpu, HEIGHT, WIDTH};
+
+// How pixels are packed into terminal cells.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextMode {
+    // 1x2 pixels per cell with ▀ ▄ █
+    HalfBlock,
+    // 2x4 pixels per cell with braille dots
+    Braille,
+}
+
+// The framebuffer as text, one string per terminal row.
+pub fn render(gpu: &Gpu, mode: TextMode) -> Vec<String> {
+    match mode {
+        TextMode::HalfBlock => half_blocks(gpu),
+        TextMode::Braille => braille(gpu),
+    }
+}
+
+fn half_blocks(gpu: &Gpu) -> Vec<String> {
+    (0..HEIGHT)
+        .step_by(2)
+        .map(|y| {
+            (0..WIDTH)
+                .map(
+                    |x| match (gpu.pixel(x, y), y + 1 < HEIGHT && gpu.pixel(x, y + 1)) {
+                        (false, false) => ' ',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (true, true) => '█',
+                    },
+                )
+                .collect()
+        })
+        .collect()
+}
+
+// bit of every dot in a braille cell, by row and column
+static DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+fn braille(gpu: &Gpu) -> Vec<String> {
+    (0..HEIGHT)
+        .step_by(4)
+        .map(|y| {
+            (0..WIDTH)
+                .step_by(2)
+                .map(|x| {
+                    let mut bits = 0;
+                    for (dy, row) in DOTS.iter().enumerate() {
+                        for (dx, bit) in row.iter().enumerate() {
+                            if y + dy < HEIGHT && x + dx < WIDTH && gpu.pixel(x + dx, y + dy) {
+                                bits |= bit;
+                            }
+                        }
+                    }
+                    ::std::char::from_u32(0x2800 + bits).unwrap()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Registers and timers for a side panel.
+pub fn registers(cpu: &Cpu) -> Vec<String> {
+    let mut lines = vec![
+        format!("PC {:03X}  I  {:03X}", cpu.pc(), cpu.i()),
+        format!("SP {:X}    DT {:02X}", cpu.sp(), cpu.delay_timer()),
+        format!("         ST {:02X}", cpu.sound_timer()),
+        String::new(),
+    ];
+    for r in 0..8 {
+        lines.push(format!(
+            "V{:X} {:02X}   V{:X} {:02X}",
+            r,
+            cpu.v()[r],
+            r + 8,
+            cpu.v()[r + 8]
+        ));
+    }
+    lines
+}